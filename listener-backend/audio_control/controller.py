@@ -62,15 +62,13 @@ def audio_controller():
             data[:fbank.shape[0], :] = fbank
             data = data.view((80, 1, 24))
             pred = model(data, src_mask)
-            pred_idx = torch.argmax(pred)
-            if pred_idx == 0:
-                event = PlayerCommand.UP.to_maze_event("Player1")
-            elif pred_idx == 1:
-                event = PlayerCommand.DOWN.to_maze_event("Player1")
-            elif pred_idx == 2:
-                event = PlayerCommand.LEFT.to_maze_event("Player1")
-            elif pred_idx == 3:
-                event = PlayerCommand.RIGHT.to_maze_event("Player1")
+            probs = torch.softmax(pred.view(-1), dim=0)
+            pred_idx = torch.argmax(probs)
+            commands = [PlayerCommand.UP, PlayerCommand.DOWN, PlayerCommand.LEFT, PlayerCommand.RIGHT]
+            if pred_idx < len(commands):
+                # Send all directions with their probabilities, the maze picks the best legal move
+                candidates = " ".join(f"{command.value}@{probs[i]:.3f}" for i, command in enumerate(commands))
+                event = f"Player1:{candidates}"
             else:
                 event = None
             if not event is None:
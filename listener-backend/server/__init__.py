@@ -2,10 +2,21 @@ import socket
 from .factory import ServerFactory
 from .protocol import ServerProtocol
 
+# Session tokens the maze hands out on connect, later commands of the player must carry them
+TOKENS = dict()
+
 
 def send_maze(event):
+    name, action = event.split(':', 1)
+    if action != 'connect' and name in TOKENS:
+        event = f"{event}:{TOKENS[name]}"
     print(event)
     maze_socket = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
     maze_socket.connect(('localhost', 8080))
-    maze_socket.send(bytes(event, 'utf-8'))
+    maze_socket.sendall(bytes(event + '\n', 'utf-8'))
+    maze_socket.shutdown(socket.SHUT_WR)
+    reply = maze_socket.makefile().readline().strip()
     maze_socket.close()
+    if action == 'connect' and reply.startswith('ok '):
+        TOKENS[name] = reply[3:]
+    return reply
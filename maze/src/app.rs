@@ -1,10 +1,12 @@
-use crate::custom_events::{handle_client, CustomEvent};
+use crate::config::Config;
+use crate::custom_events::CustomEvent;
 use crate::game::MazeGame;
 use crate::gui::Gui;
+use crate::server::Listener;
 use crate::window::AppWindow;
-use log::error;
+use log::{error, info};
 use pixels::Error;
-use std::net::TcpListener;
+use std::sync::Arc;
 use std::time::Instant;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
@@ -20,26 +22,31 @@ impl App {
         App { dt: 0., fps: 0. }
     }
 
-    pub fn run(mut self, name: &str, width: u32, height: u32, maze_size: i32) -> Result<(), Error> {
+    pub fn run(
+        mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        config: Config,
+        listeners: Vec<Listener>,
+    ) -> Result<(), Error> {
         let mut time = Instant::now();
         let event_loop = EventLoop::<CustomEvent>::with_user_event();
         let mut window = AppWindow::new(name, &event_loop, width, height);
         let mut input = WinitInputHelper::new();
         let mut frame_sum = 0.;
         let mut dt_sum = 0.;
-        let mut gui = Gui::new(&window.window(), &window.pixels());
-        let mut game = MazeGame::new(maze_size, &window);
-        let event_loop_proxy = event_loop.create_proxy();
-        let listener = TcpListener::bind("127.0.0.1:8080").expect("Failed to bind to address");
-        // Listen player connections & incoming events, handle client creates custom events based on those
-        std::thread::spawn(move || loop {
-            for stream in listener.incoming() {
-                handle_client(stream.expect("Failed to read stream"), &event_loop_proxy)
-                    .expect("Failed to handle tcp message");
-            }
-        });
+        let mut gui = Gui::new(window.window(), window.pixels());
+        let mut game = MazeGame::new(&config, &window);
+        let config = Arc::new(config);
+        // Listen player connections & incoming events, clients create custom events based on those
+        for (listener, addr) in listeners.into_iter().zip(config.listeners.iter()) {
+            info!("Listening on {}", addr);
+            listener.spawn(event_loop.create_proxy(), config.clone());
+        }
+        println!("Admin token: {}", config.admin_token);
         event_loop.run(move |event, _, control_flow| {
-            gui.handle_event(&window.window(), &event, &mut game);
+            gui.handle_event(window.window(), &event, &mut game);
             if let Event::RedrawRequested(_) = event {
                 window.clear().expect("Failed to clear");
                 // Update game (render etc...) after redraw request
@@ -55,14 +62,15 @@ impl App {
                 self.capture_fps(&mut dt_sum, &mut frame_sum, &mut time);
             }
             // Custom events must be handled before input.update, since input.update consumes events
-            // Though currently input ain't used...
             game.handle_custom_events(&event);
             if input.update(&event) {
                 if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
-                game.handle_input(&window, &input);
+                if !gui.wants_keyboard() {
+                    game.handle_input(&window, &input);
+                }
                 if let Some(size) = input.window_resized() {
                     window.resize(size);
                 }
@@ -77,7 +85,7 @@ impl App {
         self.dt = now.duration_since(*time).as_millis() as f64;
         *dt_sum += self.dt;
         if *dt_sum > 1000.0 {
-            self.fps = 1000.0 / (*dt_sum as f64 / *frame_sum as f64);
+            self.fps = 1000.0 / (*dt_sum / *frame_sum);
             *dt_sum = 0.;
             *frame_sum = 0.;
         }
@@ -0,0 +1,192 @@
+use crate::custom_events::is_valid_name;
+use crate::direction::Direction;
+use crate::maze::MazeGrid;
+use euclid::Vector2D;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Milliseconds between a bot's moves, by default
+pub const BOT_INTERVAL: f64 = 500.;
+
+// Chance of a human-like bot taking a wrong turn
+pub const MISTAKE_CHANCE: f64 = 0.2;
+
+// How a bot finds its way, easiest first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotLevel {
+    // Wanders about, turning back only at dead ends
+    Random,
+    // Keeps its right hand on the wall
+    Wall,
+    // Takes a shortest path to the goal
+    Shortest,
+    // Takes a shortest path but now and then a wrong turn, at an uneven pace
+    Human,
+}
+
+impl FromStr for BotLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(BotLevel::Random),
+            "wall" => Ok(BotLevel::Wall),
+            "shortest" => Ok(BotLevel::Shortest),
+            "human" => Ok(BotLevel::Human),
+            _ => Err(format!("Invalid bot level: {}", s)),
+        }
+    }
+}
+
+// A player the game moves by itself
+#[derive(Debug, Clone)]
+pub struct Bot {
+    pub name: String,
+    pub level: BotLevel,
+    // Milliseconds until the next move
+    cooldown: f64,
+    rng: StdRng,
+}
+
+impl Bot {
+    pub fn new(name: &str, level: BotLevel) -> Bot {
+        Bot {
+            name: name.to_string(),
+            level,
+            cooldown: 0.,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    // "robo=wall", or just "robo" for a human-like bot
+    pub fn parse(s: &str, taken: &[Bot]) -> Result<Bot, String> {
+        let (name, level) = match s.split_once('=') {
+            Some((name, level)) => (name, level.parse::<BotLevel>()?),
+            None => (s, BotLevel::Human),
+        };
+        if !is_valid_name(name) {
+            return Err(format!("Invalid bot name: {}", name));
+        }
+        if taken.iter().any(|b| b.name == name) {
+            return Err(format!("Bot {} given twice", name));
+        }
+        Ok(Bot::new(name, level))
+    }
+
+    // True when it's time to move again
+    pub fn tick(&mut self, dt: f64, interval: f64) -> bool {
+        self.cooldown -= dt;
+        if self.cooldown > 0. {
+            return false;
+        }
+        self.cooldown = match self.level {
+            BotLevel::Human => interval * self.rng.gen_range(0.5, 1.5),
+            _ => interval,
+        };
+        true
+    }
+
+    // Way to move from pos, towards the nearest of the targets if the bot knows the way
+    pub fn next_move(
+        &mut self,
+        maze: &MazeGrid,
+        pos: Vector2D<i32, i32>,
+        heading: Direction,
+        targets: &[Vector2D<i32, i32>],
+    ) -> Option<Direction> {
+        let open = (0..4)
+            .map(Direction::new)
+            .filter(|dir| maze.linked_cell(pos, *dir).is_some())
+            .collect::<Vec<Direction>>();
+        match self.level {
+            BotLevel::Random => self.wander(&open, heading),
+            BotLevel::Wall => [heading.next(), heading, heading.prev(), heading.opposite()]
+                .iter()
+                .find(|dir| open.contains(dir))
+                .copied(),
+            BotLevel::Shortest => maze
+                .first_step(pos, targets)
+                .or_else(|| self.wander(&open, heading)),
+            BotLevel::Human => {
+                let step = maze.first_step(pos, targets);
+                if step.is_none() || self.rng.gen_bool(MISTAKE_CHANCE) {
+                    self.wander(&open, heading)
+                } else {
+                    step
+                }
+            }
+        }
+    }
+
+    // Any open way but back, unless it's a dead end
+    fn wander(&mut self, open: &[Direction], heading: Direction) -> Option<Direction> {
+        let onwards = open
+            .iter()
+            .filter(|dir| **dir != heading.opposite())
+            .copied()
+            .collect::<Vec<Direction>>();
+        onwards
+            .choose(&mut self.rng)
+            .or_else(|| open.first())
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps the bot takes from the start to the goal, None if it doesn't get there in max_steps
+    fn race(level: BotLevel, maze: &MazeGrid, max_steps: usize) -> Option<usize> {
+        let mut bot = Bot::new("robo", level);
+        let (mut pos, mut heading) = (maze.start_pos(), Direction::Right);
+        for steps in 0..max_steps {
+            if pos == maze.end_pos() {
+                return Some(steps);
+            }
+            heading = bot.next_move(maze, pos, heading, &[maze.end_pos()])?;
+            pos = maze.linked_cell(pos, heading)?.pos();
+        }
+        None
+    }
+
+    #[test]
+    fn wall_bot_reaches_goal() {
+        for seed in 0..5 {
+            let maze = MazeGrid::new(10, (0, 0), (9, 9), seed);
+            // Following a wall walks each passage of the tree at most twice
+            assert!(race(BotLevel::Wall, &maze, 2 * 100).is_some());
+        }
+    }
+
+    #[test]
+    fn shortest_bot_reaches_goal() {
+        for seed in 0..5 {
+            let maze = MazeGrid::new(10, (0, 0), (9, 9), seed);
+            let steps = race(BotLevel::Shortest, &maze, 100).unwrap();
+            assert!(steps >= 18);
+        }
+    }
+
+    #[test]
+    fn random_bot_only_takes_open_ways() {
+        let maze = MazeGrid::new(10, (0, 0), (9, 9), 1);
+        let mut bot = Bot::new("robo", BotLevel::Random);
+        let (mut pos, mut heading) = (maze.start_pos(), Direction::Right);
+        for _ in 0..500 {
+            let dir = bot.next_move(&maze, pos, heading, &[]).unwrap();
+            let open = (0..4)
+                .map(Direction::new)
+                .filter(|dir| maze.linked_cell(pos, *dir).is_some())
+                .count();
+            // Turns back only at dead ends
+            assert!(dir != heading.opposite() || open == 1);
+            pos = maze.linked_cell(pos, dir).unwrap().pos();
+            heading = dir;
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use crate::bots::{Bot, BOT_INTERVAL};
+use crate::coop::{VoteRule, VOTE_WINDOW};
+use crate::keyboard::{LocalPlayer, KEY_BINDINGS};
+use crate::keyword_spotting::Voices;
+use crate::monsters::{MonsterLevel, Penalty};
+use crate::state::GameMode;
+use crate::stats::Stats;
+use crate::teams::TeamScoring;
+use crate::tournament::{Tournament, POINTS, SIZE_STEP};
+use crate::vocabulary::Vocabulary;
+use crate::{items, tag};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+// How long a player whose connection dropped is kept, by default
+pub const RECONNECT_GRACE: f64 = 30000.;
+
+// Milliseconds from everyone being ready to the race starting, by default
+pub const COUNTDOWN: f64 = 3000.;
+
+// Move interval of --voice-fairness, about how long it takes to say a command & get it recognized
+pub const VOICE_FAIRNESS_MOVE_INTERVAL: f64 = 1500.;
+
+// Where race results are kept across sessions, by default
+pub const STATS_FILE: &str = "maze_stats.json";
+
+pub const MIN_MAZE_SIZE: i32 = 2;
+pub const MAX_MAZE_SIZE: i32 = 50;
+
+pub fn parse_maze_size(value: &str) -> Result<i32, String> {
+    let maze_size = value
+        .parse::<i32>()
+        .map_err(|_| format!("Invalid maze size: {}", value))?;
+    check_maze_size(maze_size)
+}
+
+// Same range as the size slider of the game window
+pub fn check_maze_size(maze_size: i32) -> Result<i32, String> {
+    if !(MIN_MAZE_SIZE..=MAX_MAZE_SIZE).contains(&maze_size) {
+        return Err(format!(
+            "Invalid maze size, can't be less than {} or larger than {}",
+            MIN_MAZE_SIZE, MAX_MAZE_SIZE
+        ));
+    }
+    Ok(maze_size)
+}
+
+// Where clients can reach the game, given as e.g. --listen tcp://0.0.0.0:8080
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    WebSocket(SocketAddr),
+    Http(SocketAddr),
+}
+
+impl ListenAddr {
+    pub fn parse(s: &str) -> Result<ListenAddr, String> {
+        let (scheme, addr) = match s.find("://") {
+            Some(index) => (&s[..index], &s[index + 3..]),
+            None => ("tcp", s),
+        };
+        let socket_addr = || {
+            addr.parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid address {}: {}", addr, e))
+        };
+        match scheme {
+            "tcp" => Ok(ListenAddr::Tcp(socket_addr()?)),
+            "ws" => Ok(ListenAddr::WebSocket(socket_addr()?)),
+            "http" => Ok(ListenAddr::Http(socket_addr()?)),
+            "unix" if cfg!(unix) => {
+                if addr.is_empty() {
+                    Err("Missing unix socket path".to_string())
+                } else {
+                    Ok(ListenAddr::Unix(PathBuf::from(addr)))
+                }
+            }
+            _ => Err(format!("Unsupported transport: {}", scheme)),
+        }
+    }
+}
+
+impl std::fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "tcp://{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix://{}", path.display()),
+            ListenAddr::WebSocket(addr) => write!(f, "ws://{}", addr),
+            ListenAddr::Http(addr) => write!(f, "http://{}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub maze_size: i32,
+    pub listeners: Vec<ListenAddr>,
+    // Shared secret players must give to connect
+    pub password: Option<String>,
+    // Minimum milliseconds between a player's accepted moves
+    pub move_interval: f64,
+    // Words & phrases players may use for actions
+    pub vocabulary: Vocabulary,
+    // Recognized moves (e.g. "left@0.4") less confident than this are rejected
+    pub min_confidence: f32,
+    // Templates recordings sent with the audio command are recognized against
+    pub voices: Arc<RwLock<Voices>>,
+    // Players steering with keys on the game's own keyboard
+    pub local_players: Vec<LocalPlayer>,
+    // Players the game moves by itself
+    pub bots: Vec<Bot>,
+    // Milliseconds between a bot's moves
+    pub bot_interval: f64,
+    // Moves a player may undo per race, None if unlimited
+    pub undo_limit: Option<u32>,
+    // Milliseconds added to a player's move cooldown for each undo
+    pub undo_penalty: f64,
+    // Secret for admin commands, random unless given
+    pub admin_token: String,
+    // Milliseconds a player whose connection dropped is kept for reconnecting
+    pub reconnect_grace: f64,
+    // Milliseconds from everyone being ready to the race starting
+    pub countdown: f64,
+    // Skip the lobby & countdown, moves are accepted right away
+    pub no_lobby: bool,
+    // Milliseconds the race lasts at most, None if until everyone finishes
+    pub time_limit: Option<f64>,
+    // Seed of the first maze, random if None
+    pub seed: Option<u64>,
+    // Wins, best times etc. of earlier sessions, saved after each race
+    pub stats: Stats,
+    // Rounds, maze sizes & points when playing a tournament
+    pub tournament: Option<Tournament>,
+    pub mode: GameMode,
+    // How players' commands move the team's avatar in coop mode
+    pub vote_rule: VoteRule,
+    // Milliseconds votes are collected for with the majority rule
+    pub vote_window: f64,
+    // When a team has reached the goal
+    pub team_scoring: TeamScoring,
+    // Coins & gems scattered on each maze, None for one per 8 cells in score attack & none otherwise
+    pub items: Option<usize>,
+    // Monsters wandering each maze & how hard they are
+    pub monsters: usize,
+    pub monster_level: MonsterLevel,
+    // What monsters do to players they catch, None for points in score attack & back to start otherwise
+    pub monster_penalty: Option<Penalty>,
+}
+
+impl Config {
+    // Usage: maze [maze_size] [--listen <transport>://<address>]... [--password <password>]
+    //   [--move-interval <ms> | --voice-fairness] [--vocabulary <file.json>]
+    //   [--min-confidence <0..1>] [--templates <dir of wavs>]
+    //   [--local <name>[=arrows|wasd|ijkl|numpad]]... [--undo-limit <moves>] [--undo-penalty <ms>]
+    //   [--admin-token <token>] [--reconnect-grace <ms>] [--countdown <ms> | --no-lobby]
+    //   [--time-limit <ms>] [--seed <maze seed>] [--stats <file.json>]
+    //   [--rounds <n> [--size-step <cells>] [--points <1st,2nd,...>]]
+    //   [--mode race|coop|tag|score] [--vote majority|turns|directions] [--vote-window <ms>]
+    //   [--team-scoring all|first] [--items <n>]
+    //   [--monsters <n>[=easy|normal|hard]] [--monster-penalty start|points]
+    //   [--bot <name>[=random|wall|shortest|human]]... [--bots <n>[=<level>]] [--bot-interval <ms>]
+    // Giving any --listen replaces the default tcp://127.0.0.1:8080 & http://127.0.0.1:8081
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let mut maze_size = 16;
+        let mut listeners = vec![];
+        let mut password = None;
+        let mut move_interval = 0.;
+        let mut vocabulary = Vocabulary::new();
+        let mut min_confidence = 0.;
+        let mut templates = None;
+        let mut local_players = vec![];
+        let mut bots: Vec<Bot> = vec![];
+        let mut bot_interval = BOT_INTERVAL;
+        let mut undo_limit = None;
+        let mut undo_penalty = 0.;
+        let mut admin_token = Uuid::new_v4().to_string();
+        let mut reconnect_grace = RECONNECT_GRACE;
+        let mut countdown = COUNTDOWN;
+        let mut no_lobby = false;
+        let mut time_limit = None;
+        let mut seed = None;
+        let mut stats_file = PathBuf::from(STATS_FILE);
+        let mut rounds = None;
+        let mut size_step = SIZE_STEP;
+        let mut points = POINTS.to_vec();
+        let mut mode = GameMode::Race;
+        let mut vote_rule = VoteRule::Majority;
+        let mut vote_window = VOTE_WINDOW;
+        let mut team_scoring = TeamScoring::All;
+        let mut items = None;
+        let mut monsters = 0;
+        let mut monster_level = MonsterLevel::Normal;
+        let mut monster_penalty = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--listen" => {
+                    let value = args.next().ok_or("Missing value for --listen")?;
+                    listeners.push(ListenAddr::parse(value)?);
+                }
+                "--password" => {
+                    let value = args.next().ok_or("Missing value for --password")?;
+                    password = Some(value.clone());
+                }
+                "--move-interval" => {
+                    let value = args.next().ok_or("Missing value for --move-interval")?;
+                    move_interval = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid move interval: {}", value))?;
+                }
+                "--voice-fairness" => move_interval = VOICE_FAIRNESS_MOVE_INTERVAL,
+                "--vocabulary" => {
+                    let value = args.next().ok_or("Missing value for --vocabulary")?;
+                    vocabulary = Vocabulary::load(Path::new(value))?;
+                }
+                "--min-confidence" => {
+                    let value = args.next().ok_or("Missing value for --min-confidence")?;
+                    min_confidence = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|c| (0. ..=1.).contains(c))
+                        .ok_or(format!("Invalid confidence: {}", value))?;
+                }
+                "--templates" => {
+                    let value = args.next().ok_or("Missing value for --templates")?;
+                    templates = Some(PathBuf::from(value));
+                }
+                "--local" => {
+                    let value = args.next().ok_or("Missing value for --local")?;
+                    if local_players.len() == KEY_BINDINGS.len() {
+                        return Err(format!(
+                            "At most {} local players are supported",
+                            KEY_BINDINGS.len()
+                        ));
+                    }
+                    local_players.push(LocalPlayer::parse(value, &local_players)?);
+                }
+                "--undo-limit" => {
+                    let value = args.next().ok_or("Missing value for --undo-limit")?;
+                    undo_limit = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| format!("Invalid undo limit: {}", value))?,
+                    );
+                }
+                "--undo-penalty" => {
+                    let value = args.next().ok_or("Missing value for --undo-penalty")?;
+                    undo_penalty = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid undo penalty: {}", value))?;
+                }
+                "--admin-token" => {
+                    let value = args.next().ok_or("Missing value for --admin-token")?;
+                    admin_token = value.clone();
+                }
+                "--reconnect-grace" => {
+                    let value = args.next().ok_or("Missing value for --reconnect-grace")?;
+                    reconnect_grace = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid reconnect grace: {}", value))?;
+                }
+                "--countdown" => {
+                    let value = args.next().ok_or("Missing value for --countdown")?;
+                    countdown = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid countdown: {}", value))?;
+                }
+                "--no-lobby" => no_lobby = true,
+                "--time-limit" => {
+                    let value = args.next().ok_or("Missing value for --time-limit")?;
+                    time_limit = Some(
+                        value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|ms| *ms > 0.)
+                            .ok_or(format!("Invalid time limit: {}", value))?,
+                    );
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("Missing value for --seed")?;
+                    seed = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| format!("Invalid seed: {}", value))?,
+                    );
+                }
+                "--stats" => {
+                    let value = args.next().ok_or("Missing value for --stats")?;
+                    stats_file = PathBuf::from(value);
+                }
+                "--rounds" => {
+                    let value = args.next().ok_or("Missing value for --rounds")?;
+                    rounds = Some(
+                        value
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|rounds| *rounds > 0)
+                            .ok_or(format!("Invalid rounds: {}", value))?,
+                    );
+                }
+                "--size-step" => {
+                    let value = args.next().ok_or("Missing value for --size-step")?;
+                    size_step = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|step| *step >= 0)
+                        .ok_or(format!("Invalid size step: {}", value))?;
+                }
+                "--points" => {
+                    let value = args.next().ok_or("Missing value for --points")?;
+                    points = Tournament::parse_points(value)?;
+                }
+                "--mode" => {
+                    let value = args.next().ok_or("Missing value for --mode")?;
+                    mode = value.parse::<GameMode>()?;
+                }
+                "--vote" => {
+                    let value = args.next().ok_or("Missing value for --vote")?;
+                    vote_rule = value.parse::<VoteRule>()?;
+                }
+                "--vote-window" => {
+                    let value = args.next().ok_or("Missing value for --vote-window")?;
+                    vote_window = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid vote window: {}", value))?;
+                }
+                "--team-scoring" => {
+                    let value = args.next().ok_or("Missing value for --team-scoring")?;
+                    team_scoring = value.parse::<TeamScoring>()?;
+                }
+                "--bot" => {
+                    let value = args.next().ok_or("Missing value for --bot")?;
+                    bots.push(Bot::parse(value, &bots)?);
+                }
+                // Named bot1, bot2...
+                "--bots" => {
+                    let value = args.next().ok_or("Missing value for --bots")?;
+                    let (count, level) = match value.split_once('=') {
+                        Some((count, level)) => (count, Some(level)),
+                        None => (value.as_str(), None),
+                    };
+                    let count = count
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid number of bots: {}", value))?;
+                    let mut number = 0;
+                    for _ in 0..count {
+                        let name = loop {
+                            number += 1;
+                            let name = format!("bot{}", number);
+                            if !bots.iter().any(|b| b.name == name) {
+                                break name;
+                            }
+                        };
+                        let spec = match level {
+                            Some(level) => format!("{}={}", name, level),
+                            None => name,
+                        };
+                        bots.push(Bot::parse(&spec, &bots)?);
+                    }
+                }
+                "--bot-interval" => {
+                    let value = args.next().ok_or("Missing value for --bot-interval")?;
+                    bot_interval = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ms| *ms >= 0.)
+                        .ok_or(format!("Invalid bot interval: {}", value))?;
+                }
+                "--monsters" => {
+                    let value = args.next().ok_or("Missing value for --monsters")?;
+                    let count = match value.split_once('=') {
+                        Some((count, level)) => {
+                            monster_level = level.parse::<MonsterLevel>()?;
+                            count
+                        }
+                        None => value,
+                    };
+                    monsters = count
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid number of monsters: {}", value))?;
+                }
+                "--monster-penalty" => {
+                    let value = args.next().ok_or("Missing value for --monster-penalty")?;
+                    monster_penalty = Some(value.parse::<Penalty>()?);
+                }
+                "--items" => {
+                    let value = args.next().ok_or("Missing value for --items")?;
+                    items = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| format!("Invalid number of items: {}", value))?,
+                    );
+                }
+                option if option.starts_with("--") => {
+                    return Err(format!("Unknown option: {}", option));
+                }
+                value => maze_size = parse_maze_size(value)?,
+            }
+        }
+        if listeners.is_empty() {
+            listeners = vec![
+                ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 8080))),
+                ListenAddr::Http(SocketAddr::from(([127, 0, 0, 1], 8081))),
+            ];
+        }
+        // Loaded last, since template file names go through the vocabulary
+        let voices = match templates {
+            Some(dir) => Voices::load(&dir, &vocabulary)?,
+            None => Voices::default(),
+        };
+        let stats = Stats::load(&stats_file)?;
+        // Tag & score attack have no goal to reach, rounds end when the time is up
+        if time_limit.is_none() {
+            time_limit = match mode {
+                GameMode::Tag => Some(tag::ROUND_LENGTH),
+                GameMode::Score => Some(items::ROUND_LENGTH),
+                _ => None,
+            };
+        }
+        let tournament = rounds.map(|rounds| Tournament::new(rounds, maze_size, size_step, points));
+        Ok(Config {
+            maze_size,
+            listeners,
+            password,
+            move_interval,
+            vocabulary,
+            min_confidence,
+            voices: Arc::new(RwLock::new(voices)),
+            local_players,
+            bots,
+            bot_interval,
+            undo_limit,
+            undo_penalty,
+            admin_token,
+            reconnect_grace,
+            countdown,
+            no_lobby,
+            time_limit,
+            seed,
+            stats,
+            tournament,
+            mode,
+            vote_rule,
+            vote_window,
+            team_scoring,
+            items,
+            monsters,
+            monster_level,
+            monster_penalty,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_listen_addrs() {
+        assert_eq!(
+            ListenAddr::parse("tcp://127.0.0.1:8080"),
+            Ok(ListenAddr::Tcp(socket("127.0.0.1:8080")))
+        );
+        assert_eq!(
+            ListenAddr::parse("0.0.0.0:8080"),
+            Ok(ListenAddr::Tcp(socket("0.0.0.0:8080")))
+        );
+        assert_eq!(
+            ListenAddr::parse("ws://[::1]:9000"),
+            Ok(ListenAddr::WebSocket(socket("[::1]:9000")))
+        );
+        assert_eq!(
+            ListenAddr::parse("http://127.0.0.1:8081"),
+            Ok(ListenAddr::Http(socket("127.0.0.1:8081")))
+        );
+        if cfg!(unix) {
+            assert_eq!(
+                ListenAddr::parse("unix:///tmp/maze.sock"),
+                Ok(ListenAddr::Unix(PathBuf::from("/tmp/maze.sock")))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_listen_addrs() {
+        assert!(ListenAddr::parse("tcp://localhost").is_err());
+        assert!(ListenAddr::parse("ws://").is_err());
+        assert!(ListenAddr::parse("8080").is_err());
+        assert_eq!(
+            ListenAddr::parse("udp://127.0.0.1:8080"),
+            Err("Unsupported transport: udp".to_string())
+        );
+        assert!(ListenAddr::parse("unix://").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        let args = ["--no-lobby", "--bogus"].iter().map(|s| s.to_string());
+        assert_eq!(
+            Config::from_args(&args.collect::<Vec<String>>()).err(),
+            Some("Unknown option: --bogus".to_string())
+        );
+    }
+}
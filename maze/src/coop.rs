@@ -0,0 +1,224 @@
+use crate::custom_events::Movement;
+use crate::direction::{Direction, Steering};
+use crate::state::Vote;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Avatar everyone steers together in coop mode, no one can connect with the name
+pub const TEAM_NAME: &str = "team";
+
+// Milliseconds votes are collected for, from the first one, by default
+pub const VOTE_WINDOW: f64 = 2000.;
+
+// How the players' commands become moves of the team's avatar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteRule {
+    // Most voted move wins once everyone has voted or the vote window closes
+    Majority,
+    // Players take turns, a move each
+    Turns,
+    // Directions are dealt out to players, who may only move (or turn) to their own
+    Directions,
+}
+
+impl FromStr for VoteRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "majority" => Ok(VoteRule::Majority),
+            "turns" => Ok(VoteRule::Turns),
+            "directions" => Ok(VoteRule::Directions),
+            _ => Err(format!("Invalid vote rule: {}", s)),
+        }
+    }
+}
+
+// Votes for the avatar's next move, the latest one of each player
+#[derive(Debug, Clone, Default)]
+pub struct Votes {
+    votes: Vec<(String, Steering, Movement)>,
+    // Milliseconds left to vote
+    window: Option<f64>,
+}
+
+impl Votes {
+    pub fn add(&mut self, voter: &str, steering: Steering, movement: Movement, window: f64) {
+        self.forget(voter);
+        self.votes.push((voter.to_string(), steering, movement));
+        if self.window.is_none() {
+            self.window = Some(window);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.votes.len()
+    }
+
+    // True once the vote window has closed
+    pub fn tick(&mut self, dt: f64) -> bool {
+        match self.window.as_mut() {
+            Some(window) => {
+                *window -= dt;
+                *window <= 0.
+            }
+            None => false,
+        }
+    }
+
+    // Most voted move, ties go to the one voted first. Starts a new vote.
+    pub fn take_winner(&mut self) -> Option<(Steering, Movement)> {
+        let mut counts: Vec<((Steering, Movement), usize)> = vec![];
+        for (_, steering, movement) in self.votes.iter() {
+            match counts
+                .iter_mut()
+                .find(|(vote, _)| *vote == (*steering, *movement))
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push(((*steering, *movement), 1)),
+            }
+        }
+        self.clear();
+        let mut winner: Option<((Steering, Movement), usize)> = None;
+        for (vote, count) in counts {
+            if winner.is_none_or(|(_, most)| count > most) {
+                winner = Some((vote, count));
+            }
+        }
+        winner.map(|(vote, _)| vote)
+    }
+
+    pub fn clear(&mut self) {
+        self.votes.clear();
+        self.window = None;
+    }
+
+    pub fn forget(&mut self, voter: &str) {
+        self.votes.retain(|(name, _, _)| name != voter);
+    }
+
+    pub fn rename(&mut self, voter: &str, new_name: &str) {
+        for (name, _, _) in self.votes.iter_mut().filter(|(name, _, _)| name == voter) {
+            *name = new_name.to_string();
+        }
+    }
+
+    pub fn list(&self) -> Vec<Vote> {
+        self.votes
+            .iter()
+            .map(|(name, steering, movement)| Vote {
+                name: name.clone(),
+                vote: describe(*steering, *movement),
+            })
+            .collect()
+    }
+}
+
+// E.g. "left", "left 3" or "run left"
+pub fn describe(steering: Steering, movement: Movement) -> String {
+    match movement {
+        Movement::Run => format!("run {}", steering),
+        Movement::Steps(1) => steering.to_string(),
+        Movement::Steps(steps) => format!("{} {}", steering, steps),
+    }
+}
+
+// Voter after current one in the (sorted) voters, wrapping around
+pub fn next_turn(voters: &[String], current: &str) -> Option<String> {
+    voters
+        .iter()
+        .find(|voter| voter.as_str() > current)
+        .or_else(|| voters.first())
+        .cloned()
+}
+
+// Directions are dealt out to the (sorted) voters in order up, right, down, left
+pub fn direction_owner(voters: &[String], direction: Direction) -> Option<&String> {
+    if voters.is_empty() {
+        None
+    } else {
+        voters.get(direction.to_int() % voters.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn most_voted_move_wins() {
+        let mut votes = Votes::default();
+        let left = Steering::Absolute(Direction::Left);
+        let up = Steering::Absolute(Direction::Up);
+        votes.add("okko", up, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("ville", left, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("aino", left, Movement::Steps(1), VOTE_WINDOW);
+        assert_eq!(votes.take_winner(), Some((left, Movement::Steps(1))));
+        assert_eq!(votes.len(), 0);
+        assert_eq!(votes.take_winner(), None);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_vote() {
+        let mut votes = Votes::default();
+        let left = Steering::Absolute(Direction::Left);
+        let up = Steering::Absolute(Direction::Up);
+        votes.add("okko", up, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("ville", left, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("aino", left, Movement::Run, VOTE_WINDOW);
+        votes.add("eero", up, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("kaisa", left, Movement::Steps(1), VOTE_WINDOW);
+        assert_eq!(votes.take_winner(), Some((up, Movement::Steps(1))));
+    }
+
+    #[test]
+    fn a_new_vote_replaces_the_old_one() {
+        let mut votes = Votes::default();
+        let left = Steering::Absolute(Direction::Left);
+        let up = Steering::Absolute(Direction::Up);
+        votes.add("okko", up, Movement::Steps(1), VOTE_WINDOW);
+        votes.add("okko", left, Movement::Steps(1), VOTE_WINDOW);
+        assert_eq!(votes.len(), 1);
+        assert_eq!(votes.take_winner(), Some((left, Movement::Steps(1))));
+    }
+
+    #[test]
+    fn vote_window_closes() {
+        let mut votes = Votes::default();
+        assert!(!votes.tick(VOTE_WINDOW));
+        votes.add("okko", Steering::Forward, Movement::Steps(1), VOTE_WINDOW);
+        assert!(!votes.tick(VOTE_WINDOW / 2.));
+        assert!(votes.tick(VOTE_WINDOW / 2.));
+    }
+
+    #[test]
+    fn turns_wrap_around() {
+        let voters = names(&["aino", "okko", "ville"]);
+        assert_eq!(next_turn(&voters, "aino").as_deref(), Some("okko"));
+        assert_eq!(next_turn(&voters, "ville").as_deref(), Some("aino"));
+        // Whoever had the turn may have left
+        assert_eq!(next_turn(&voters, "eero").as_deref(), Some("okko"));
+        assert_eq!(next_turn(&[], "okko"), None);
+    }
+
+    #[test]
+    fn directions_are_dealt_out_to_fewer_voters() {
+        let voters = names(&["aino", "okko", "ville"]);
+        let owner = |direction| direction_owner(&voters, direction).map(|o| o.as_str());
+        assert_eq!(owner(Direction::Up), Some("aino"));
+        assert_eq!(owner(Direction::Right), Some("okko"));
+        assert_eq!(owner(Direction::Down), Some("ville"));
+        assert_eq!(owner(Direction::Left), Some("aino"));
+        let alone = names(&["okko"]);
+        assert_eq!(
+            direction_owner(&alone, Direction::Left).map(|o| o.as_str()),
+            Some("okko")
+        );
+        assert_eq!(direction_owner(&[], Direction::Up), None);
+    }
+}
@@ -1,81 +1,691 @@
-use crate::direction::Direction;
-use std::io::{Error as StdError, Read};
-use std::net::TcpStream;
-use std::str;
+use crate::bots::BotLevel;
+use crate::config::{parse_maze_size, Config};
+use crate::direction::{Direction, Steering};
+use crate::keyword_spotting::{hear, Utterance};
+use crate::mfcc::{Recording, MAX_RECORDING_SECONDS, RAW_PCM_SAMPLE_RATE};
+use crate::server::Connection;
+use crate::state::{GameEvent, GameState, MazeState, Phase};
+use crate::stats::PlayerStats;
+use crate::vocabulary::Vocabulary;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Error as StdError;
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
 use winit::event_loop::EventLoopProxy;
 
-// Events like: echo "okko:connect" | nc localhost 8080
-// echo "okko:disconnect" | nc localhost 8080
-// echo "okko:left" | nc localhost 8080
-pub fn handle_client(
-    mut stream: TcpStream,
+// How long a client waits for the game loop to answer
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Outcome of a player command, game answers through it
+pub type Reply = Sender<Result<String, CommandError>>;
+
+// Commands from this "player" are admin commands, no one can connect with the name
+pub const ADMIN_NAME: &str = "admin";
+
+// Player, bot & team names are single words without the command separator
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(':') && !name.contains(char::is_whitespace)
+}
+
+// Largest recording the audio command accepts, the longest one of 16 kHz 16 bit mono pcm
+// with room for a wav header
+pub const MAX_AUDIO_BYTES: usize =
+    (RAW_PCM_SAMPLE_RATE * 2 * MAX_RECORDING_SECONDS) as usize + 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    Unauthorized,
+    UnknownPlayer(String),
+    NameTaken(String),
+    InvalidName(String),
+    // Milliseconds until the player may move again
+    TooFast(u64),
+    Blocked(Direction),
+    // Confidence of the best recognized candidate
+    LowConfidence(f32),
+    // Recording couldn't be used, with the reason
+    Unrecognized(String),
+    NothingToUndo,
+    // Player has used up this many undos
+    UndoLimit(u32),
+    Paused,
+    // Moves are only accepted while the race is on
+    NotPlaying(Phase),
+    // Player has already reached the goal
+    AlreadyFinished,
+    // In coop mode with turns, whose turn it is
+    NotYourTurn(String),
+    // In coop mode with directions, the direction someone else owns
+    NotYourDirection(Direction),
+    NoResponse,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Unauthorized => write!(f, "unauthorized"),
+            CommandError::UnknownPlayer(name) => write!(f, "unknown player {}", name),
+            CommandError::NameTaken(name) => write!(f, "name {} is taken", name),
+            CommandError::InvalidName(name) => write!(f, "invalid name {}", name),
+            CommandError::TooFast(ms) => write!(f, "too fast, wait {} ms", ms),
+            CommandError::Blocked(dir) => write!(f, "wall blocks {:?}", dir),
+            CommandError::LowConfidence(confidence) => {
+                write!(f, "not confident enough ({:.2})", confidence)
+            }
+            CommandError::Unrecognized(reason) => write!(f, "{}", reason),
+            CommandError::NothingToUndo => write!(f, "nothing to undo"),
+            CommandError::UndoLimit(limit) => write!(f, "all {} undos used", limit),
+            CommandError::Paused => write!(f, "game is paused"),
+            CommandError::NotPlaying(phase) => {
+                write!(
+                    f,
+                    "race is not on ({})",
+                    format!("{:?}", phase).to_lowercase()
+                )
+            }
+            CommandError::AlreadyFinished => write!(f, "already finished"),
+            CommandError::NotYourTurn(name) => write!(f, "not your turn, {} moves", name),
+            CommandError::NotYourDirection(dir) => write!(f, "{:?} is not your direction", dir),
+            CommandError::NoResponse => write!(f, "game did not respond"),
+        }
+    }
+}
+
+// How far a single move command goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Movement {
+    // Up to n cells, stopping early at a wall
+    Steps(u32),
+    // Until the next junction or dead end
+    Run,
+}
+
+// How a player's commands are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlMode {
+    // Directions are compass directions on screen
+    Absolute,
+    // Ego-centric, player has a heading and steers forward/back/turn left/turn right
+    Relative,
+}
+
+impl FromStr for ControlMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(ControlMode::Absolute),
+            "relative" => Ok(ControlMode::Relative),
+            _ => Err(format!("Invalid control mode: {}", s)),
+        }
+    }
+}
+
+// A parsed "name:action [args][:secret]" line, where secret is the server password for connect
+// and the session token (answer to connect) for everything else
+struct ClientCommand {
+    name: String,
+    action: String,
+    args: Vec<String>,
+    // Recognized moves with their confidence, from "left@0.7 up@0.2"
+    candidates: Vec<(String, f32)>,
+    secret: Option<String>,
+}
+
+impl ClientCommand {
+    // Action words go through the vocabulary, so "okko:vasen" is the same as "okko:left"
+    fn parse(message: &str, vocabulary: &Vocabulary) -> Option<ClientCommand> {
+        let parts = message.split(':').collect::<Vec<&str>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return None;
+        }
+        let mut candidates = vec![];
+        let mut words = vec![];
+        for word in parts[1].split_whitespace() {
+            match word.split_once('@') {
+                Some((candidate, confidence)) => {
+                    candidates.push((candidate, confidence.parse::<f32>().ok()?))
+                }
+                None => words.push(word),
+            }
+        }
+        // Other words go with each candidate, "turn left@0.7 right@0.2" is "turn left" or "turn right"
+        let candidates = candidates
+            .into_iter()
+            .map(|(candidate, confidence)| {
+                let mut phrase = words.clone();
+                phrase.push(candidate);
+                (vocabulary.translate(&phrase.join(" ")), confidence)
+            })
+            .collect::<Vec<(String, f32)>>();
+        let action = match candidates.first() {
+            Some((candidate, _)) => candidate.clone(),
+            None => vocabulary.translate(&words.join(" ")),
+        };
+        let mut words = action.split_whitespace().map(|s| s.to_string());
+        Some(ClientCommand {
+            name: parts[0].split_whitespace().next()?.to_string(),
+            action: words.next()?,
+            args: words.collect(),
+            candidates,
+            secret: parts
+                .get(2)
+                .and_then(|s| s.split_whitespace().next())
+                .map(|s| s.to_string()),
+        })
+    }
+
+    // "left", "left 3", "run left", "forward", "turn left"...
+    fn movement(&self) -> Result<(Steering, Movement), String> {
+        match (self.action.as_str(), self.args.as_slice()) {
+            ("run", [direction]) => Ok((direction.parse()?, Movement::Run)),
+            ("turn", [direction]) => {
+                Ok((format!("turn {}", direction).parse()?, Movement::Steps(1)))
+            }
+            (direction, []) => Ok((direction.parse()?, Movement::Steps(1))),
+            (direction, [steps]) => {
+                let steps = steps
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|steps| *steps > 0)
+                    .ok_or(format!("Invalid step count: {}", steps))?;
+                Ok((direction.parse()?, Movement::Steps(steps)))
+            }
+            _ => Err(format!("Invalid command: {}", self.action)),
+        }
+    }
+}
+
+// Events like: echo "okko:connect" | nc localhost 8080 (answers "ok <token>")
+// echo "okko:disconnect:<token>" | nc localhost 8080
+// echo "okko:left:<token>" | nc localhost 8080 (answers "ok <cells moved>")
+// echo "okko:left 3:<token>" | nc localhost 8080 moves up to 3 cells
+// echo "okko:run left:<token>" | nc localhost 8080 moves until the next junction or dead end
+// echo "okko:left@0.7 up@0.2:<token>" | nc localhost 8080 moves to the most confident candidate
+// that is a legal move (answers "ok <cells moved> <candidate>")
+// echo "okko:ready:<token>" | nc localhost 8080 readies the player in the lobby, once everyone is ready
+// the countdown starts and after it moves are accepted
+// echo "okko:undo:<token>" | nc localhost 8080 takes back the player's last move, "okko:undo 3" the
+// last three (answers "ok <moves undone>")
+// echo "okko:team red:<token>" | nc localhost 8080 joins team red, "okko:team" leaves the team
+// echo "okko:mode relative:<token>" | nc localhost 8080 switches to steering with
+// forward/back/turn left/turn right (or up/down/left/right) relative to the player's heading
+// Each line is its own command and gets an "ok ..." or "error ..." line back. A connection can
+// also stay open and send several, then the token can be left out after connecting.
+// With a server password, connect with "okko:connect:<password>".
+// "okko:audio <byte count>:<token>" followed by that many bytes of wav or raw 16 kHz 16 bit pcm
+// moves by the command recognized from the recording (needs --templates or enrolling)
+// echo "okko:enroll:<token>" | nc localhost 8080 starts recording the player's own templates, answers
+// with the word to say next ("ok up 1/3"), and the following audio commands are stored as its templates
+// until enrollment answers "ok done"
+// Admin commands are like "admin:kick okko:<admin token>", see admin_event
+// A connection that stays open sends "hold" (websocket connections hold without asking), then if it
+// drops, the players it connected are kept for --reconnect-grace and can be reclaimed from a new
+// connection with "okko:reconnect:<token>" (answers "ok <name>", the token decides which player).
+// One-shot connections closing is no reason to drop their players.
+// Any command of a dropped player with their token reclaims them too.
+// echo "stats" | nc localhost 8080 answers with the leaderboard of all sessions as json,
+// "stats okko" with okko's wins, streaks & best times
+// nc localhost 8080 <<< "subscribe" turns the connection into a stream of game events
+pub fn handle_client<C: Connection>(
+    connection: &mut C,
     event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    config: &Config,
 ) -> Result<(), StdError> {
-    println!("Connection from {}", stream.peer_addr()?);
-    let mut buf = Vec::with_capacity(256);
-    stream
-        .read_to_end(&mut buf)
-        .expect("Failed to read stream to end");
-    let message = match str::from_utf8(&buf) {
-        Ok(v) => v,
-        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-    };
-    let parts = message.split(":").collect::<Vec<&str>>();
-    match parts.len() {
-        2 => {
-            let name_str = parts[0].split_whitespace().next().unwrap();
-            let action_str = parts[1].split_whitespace().next().unwrap();
-            match action_str {
-                "connect" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerConnected(name_str.to_string()))
-                        .ok();
+    println!("Connection from {}", connection.peer());
+    // Tokens of the players connected through this connection
+    let mut tokens = HashMap::<String, String>::new();
+    let mut held = connection.holds_players();
+    let result = handle_commands(connection, event_loop_proxy, config, &mut tokens, &mut held);
+    if !held {
+        return result;
+    }
+    for (name, token) in tokens {
+        event_loop_proxy
+            .send_event(CustomEvent::PlayerDropped(name, token))
+            .ok();
+    }
+    result
+}
+
+fn handle_commands<C: Connection>(
+    connection: &mut C,
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    config: &Config,
+    tokens: &mut HashMap<String, String>,
+    held: &mut bool,
+) -> Result<(), StdError> {
+    while let Some(line) = connection.read_line()? {
+        match config.vocabulary.translate(&line).as_str() {
+            "subscribe" => return stream_events(connection, event_loop_proxy),
+            "hold" => {
+                *held = true;
+                connection.write_line("ok")?;
+                continue;
+            }
+            _ => (),
+        }
+        // Names keep their case, only the action goes through the vocabulary
+        let is_stats = |word: &str| config.vocabulary.translate(word) == "stats";
+        let stats = match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [action] if is_stats(action) => Some(query_stats(event_loop_proxy, None)),
+            [action, name] if is_stats(action) => Some(query_stats(event_loop_proxy, Some(name))),
+            _ => None,
+        };
+        if let Some(stats) = stats {
+            match stats {
+                Ok(json) => connection.write_line(&format!("ok {}", json))?,
+                Err(e) => connection.write_line(&format!("error {}", e))?,
+            }
+            continue;
+        }
+        if let Some(admin_command) = line.strip_prefix(&format!("{}:", ADMIN_NAME)) {
+            let (reply, receiver) = channel();
+            match admin_event(admin_command, config, reply) {
+                Ok(event) => {
+                    event_loop_proxy.send_event(event).ok();
+                    match wait_reply(&receiver) {
+                        Ok(message) => {
+                            connection.write_line(format!("ok {}", message).trim_end())?
+                        }
+                        Err(e) => connection.write_line(&format!("error {}", e))?,
+                    }
+                }
+                Err(e) => connection.write_line(&format!("error {}", e))?,
+            }
+            continue;
+        }
+        let mut command = match ClientCommand::parse(&line, &config.vocabulary) {
+            Some(command) => command,
+            // Blank lines (e.g. a trailing newline) don't need an answer
+            None if line.trim().is_empty() => continue,
+            None => {
+                connection.write_line(&format!("error Invalid command: {}", line))?;
+                continue;
+            }
+        };
+        if command.secret.is_none() {
+            command.secret = tokens.get(&command.name).cloned();
+        }
+        let (reply, receiver) = channel();
+        let secret = command.secret.clone().unwrap_or_default();
+        let event = match command.action.as_str() {
+            "connect" => {
+                if config.password.is_some() && command.secret != config.password {
+                    connection.write_line(&format!("error {}", CommandError::Unauthorized))?;
+                    continue;
+                }
+                CustomEvent::PlayerConnected(command.name.clone(), reply)
+            }
+            "reconnect" => match &command.secret {
+                Some(token) => CustomEvent::PlayerReconnected(token.clone(), reply),
+                None => {
+                    connection.write_line(&format!("error {}", CommandError::Unauthorized))?;
+                    continue;
                 }
-                "disconnect" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerDisconnected(name_str.to_string()))
-                        .ok();
+            },
+            "disconnect" => CustomEvent::PlayerDisconnected(
+                command.name.clone(),
+                command.secret.unwrap_or_default(),
+                reply,
+            ),
+            "enroll" => CustomEvent::PlayerEnroll(
+                command.name.clone(),
+                command.secret.unwrap_or_default(),
+                reply,
+            ),
+            "ready" => CustomEvent::PlayerReady(
+                command.name.clone(),
+                command.secret.unwrap_or_default(),
+                reply,
+            ),
+            "undo" => match command.args.first().map(|moves| moves.parse::<u32>()) {
+                None => CustomEvent::PlayerUndo(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    1,
+                    reply,
+                ),
+                Some(Ok(moves)) if moves > 0 => CustomEvent::PlayerUndo(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    moves,
+                    reply,
+                ),
+                _ => {
+                    connection.write_line("error Invalid undo count")?;
+                    continue;
                 }
-                "up" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerMove(name_str.to_string(), Direction::Up))
-                        .ok();
+            },
+            "team" => match command.args.as_slice() {
+                [] => CustomEvent::PlayerSetTeam(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    None,
+                    reply,
+                ),
+                [team] if is_valid_name(team) => CustomEvent::PlayerSetTeam(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    Some(team.clone()),
+                    reply,
+                ),
+                _ => {
+                    connection.write_line("error Invalid team name")?;
+                    continue;
                 }
-                "right" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerMove(
-                            name_str.to_string(),
-                            Direction::Right,
-                        ))
-                        .ok();
+            },
+            "mode" => match command.args.join(" ").parse::<ControlMode>() {
+                Ok(mode) => CustomEvent::PlayerSetMode(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    mode,
+                    reply,
+                ),
+                Err(e) => {
+                    connection.write_line(&format!("error {}", e))?;
+                    continue;
                 }
-                "down" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerMove(
-                            name_str.to_string(),
-                            Direction::Down,
-                        ))
-                        .ok();
+            },
+            "audio" => {
+                let len = command
+                    .args
+                    .first()
+                    .and_then(|len| len.parse::<usize>().ok())
+                    .filter(|len| *len > 0 && *len <= MAX_AUDIO_BYTES);
+                let len = match len {
+                    Some(len) => len,
+                    None => {
+                        connection.write_line("error Invalid audio length")?;
+                        continue;
+                    }
+                };
+                let bytes = connection.read_bytes(len)?;
+                match Recording::decode(&bytes) {
+                    Ok(recording) => CustomEvent::PlayerAudio(
+                        command.name.clone(),
+                        command.secret.unwrap_or_default(),
+                        hear(&config.voices, &command.name, recording),
+                        reply,
+                    ),
+                    Err(e) => {
+                        connection.write_line(&format!("error {}", e))?;
+                        continue;
+                    }
                 }
-                "left" => {
-                    event_loop_proxy
-                        .send_event(CustomEvent::PlayerMove(
-                            name_str.to_string(),
-                            Direction::Left,
-                        ))
-                        .ok();
+            }
+            _ if !command.candidates.is_empty() => {
+                let candidates = command
+                    .candidates
+                    .iter()
+                    .map(|(steering, confidence)| Ok((steering.parse::<Steering>()?, *confidence)))
+                    .collect::<Result<Vec<(Steering, f32)>, String>>();
+                match candidates {
+                    Ok(candidates) => CustomEvent::PlayerMoveGuess(
+                        command.name.clone(),
+                        command.secret.unwrap_or_default(),
+                        candidates,
+                        reply,
+                    ),
+                    Err(e) => {
+                        connection.write_line(&format!("error {}", e))?;
+                        continue;
+                    }
                 }
-                _ => (),
             }
+            _ => match command.movement() {
+                Ok((steering, movement)) => CustomEvent::PlayerMove(
+                    command.name.clone(),
+                    command.secret.unwrap_or_default(),
+                    steering,
+                    movement,
+                    reply,
+                ),
+                Err(e) => {
+                    connection.write_line(&format!("error {}", e))?;
+                    continue;
+                }
+            },
+        };
+        event_loop_proxy.send_event(event).ok();
+        match wait_reply(&receiver) {
+            Ok(message) => {
+                match command.action.as_str() {
+                    "connect" => {
+                        tokens.insert(command.name, message.clone());
+                    }
+                    "reconnect" => {
+                        tokens.insert(message.clone(), secret);
+                    }
+                    "disconnect" => {
+                        tokens.remove(&command.name);
+                    }
+                    _ => (),
+                }
+                connection.write_line(format!("ok {}", message).trim_end())?;
+            }
+            Err(e) => connection.write_line(&format!("error {}", e))?,
+        }
+    }
+    Ok(())
+}
+
+// Part of an admin line after "admin:", e.g. "restart:<admin token>". Commands:
+// restart, regenerate [maze size], kick <name>, rename <name> <new name>, reset <name> (back to start),
+// pause, resume, start (countdown without waiting for everyone to be ready),
+// bot <name> [random|wall|shortest|human] (adds a bot player)
+fn admin_event(line: &str, config: &Config, reply: Reply) -> Result<CustomEvent, String> {
+    let (command, token) = line.rsplit_once(':').unwrap_or((line, ""));
+    if token.trim() != config.admin_token {
+        return Err(CommandError::Unauthorized.to_string());
+    }
+    match command.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        ["restart"] | ["regenerate"] => Ok(CustomEvent::Regenerate(None, reply)),
+        ["regenerate", size] => Ok(CustomEvent::Regenerate(Some(parse_maze_size(size)?), reply)),
+        ["kick", name] => Ok(CustomEvent::KickPlayer(name.to_string(), reply)),
+        ["rename", name, new_name] => Ok(CustomEvent::RenamePlayer(
+            name.to_string(),
+            new_name.to_string(),
+            reply,
+        )),
+        ["reset", name] => Ok(CustomEvent::ResetPlayer(name.to_string(), reply)),
+        ["pause"] => Ok(CustomEvent::SetPaused(true, reply)),
+        ["resume"] => Ok(CustomEvent::SetPaused(false, reply)),
+        ["start"] => Ok(CustomEvent::StartCountdown(reply)),
+        ["bot", name] => Ok(CustomEvent::AddBot(
+            name.to_string(),
+            BotLevel::Human,
+            reply,
+        )),
+        ["bot", name, level] => Ok(CustomEvent::AddBot(name.to_string(), level.parse()?, reply)),
+        _ => Err(format!("Invalid admin command: {}", command)),
+    }
+}
+
+pub fn wait_reply(
+    receiver: &Receiver<Result<String, CommandError>>,
+) -> Result<String, CommandError> {
+    receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or(Err(CommandError::NoResponse))
+}
+
+// Leaderboard as json, or the stats of the named player
+fn query_stats(
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    name: Option<&str>,
+) -> Result<String, CommandError> {
+    let (sender, receiver) = channel();
+    event_loop_proxy
+        .send_event(CustomEvent::StatsRequested(sender))
+        .ok();
+    let leaderboard = receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| CommandError::NoResponse)?;
+    let json = match name {
+        Some(name) => {
+            let player = leaderboard
+                .iter()
+                .find(|p| p.name == name)
+                .ok_or_else(|| CommandError::UnknownPlayer(name.to_string()))?;
+            serde_json::to_string(player)
         }
-        _ => (),
+        None => serde_json::to_string(&leaderboard),
+    };
+    Ok(json.unwrap_or_default())
+}
+
+// Writes each game event as a json line until the client goes away
+fn stream_events<C: Connection>(
+    connection: &mut C,
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+) -> Result<(), StdError> {
+    let (sender, receiver) = channel();
+    event_loop_proxy
+        .send_event(CustomEvent::Subscribe(sender))
+        .ok();
+    for event in receiver {
+        let json = serde_json::to_string(&event)?;
+        connection.write_line(&json)?;
     }
     Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub enum CustomEvent {
-    PlayerConnected(String),
-    PlayerMove(String, Direction),
-    PlayerDisconnected(String),
+    // Player name, answers with the player's session token
+    PlayerConnected(String, Reply),
+    // Player name, session token, ..., answers with the number of cells moved
+    PlayerMove(String, String, Steering, Movement, Reply),
+    // Player name, session token, recognized candidates with confidence
+    PlayerMoveGuess(String, String, Vec<(Steering, f32)>, Reply),
+    // Player name, session token, recording recognized as move candidates or kept as a template
+    // when enrolling, answers like PlayerMoveGuess or with the next word to enroll
+    PlayerAudio(String, String, Utterance, Reply),
+    // Player name, session token, answers with the first word to enroll
+    PlayerEnroll(String, String, Reply),
+    PlayerReady(String, String, Reply),
+    // Player name, session token, moves to undo, answers with the number of moves undone
+    PlayerUndo(String, String, u32, Reply),
+    PlayerSetMode(String, String, ControlMode, Reply),
+    // Player name, session token, team to join or None to leave
+    PlayerSetTeam(String, String, Option<String>, Reply),
+    PlayerDisconnected(String, String, Reply),
+    // Player name, session token, when the connection the player used went away without disconnecting
+    PlayerDropped(String, String),
+    // Session token of a dropped player, answers with the player's name
+    PlayerReconnected(String, Reply),
+    // Admin commands, the admin token has already been checked
+    // New maze, optionally of another size
+    Regenerate(Option<i32>, Reply),
+    KickPlayer(String, Reply),
+    // Player name, new name
+    RenamePlayer(String, String, Reply),
+    // Sends player back to start
+    ResetPlayer(String, Reply),
+    SetPaused(bool, Reply),
+    // Starts the countdown from the lobby, ready or not
+    StartCountdown(Reply),
+    // Bot's name & level
+    AddBot(String, BotLevel, Reply),
+    // Queries, game answers through the given sender
+    StateRequested(Sender<GameState>),
+    MazeRequested(Sender<MazeState>),
+    // Players of all sessions, best first
+    StatsRequested(Sender<Vec<PlayerStats>>),
+    // Game keeps sending events to the subscriber until it hangs up
+    Subscribe(Sender<GameEvent>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Direction;
+
+    fn parse(message: &str) -> Option<ClientCommand> {
+        ClientCommand::parse(message, &Vocabulary::new())
+    }
+
+    #[test]
+    fn parses_name_action_and_secret() {
+        let command = parse("okko:left 3:token").unwrap();
+        assert_eq!(command.name, "okko");
+        assert_eq!(command.action, "left");
+        assert_eq!(command.args, vec!["3"]);
+        assert_eq!(command.secret.as_deref(), Some("token"));
+        assert!(command.candidates.is_empty());
+    }
+
+    #[test]
+    fn parses_candidates() {
+        let command = parse("okko:vasen@0.6 up@0.3:token").unwrap();
+        assert_eq!(command.action, "left");
+        assert_eq!(
+            command.candidates,
+            vec![("left".to_string(), 0.6), ("up".to_string(), 0.3)]
+        );
+    }
+
+    #[test]
+    fn parses_candidates_with_other_words() {
+        let command = parse("okko:turn left@0.7 right@0.2:token").unwrap();
+        assert_eq!(
+            command.candidates,
+            vec![
+                ("turn left".to_string(), 0.7),
+                ("turn right".to_string(), 0.2)
+            ]
+        );
+        let command = parse("okko:käänny vasemmalle@0.7").unwrap();
+        assert_eq!(command.candidates, vec![("turn left".to_string(), 0.7)]);
+        // Candidates are single moves, so running isn't mistaken for a step
+        let command = parse("okko:run left@0.9").unwrap();
+        assert_eq!(command.candidates, vec![("run left".to_string(), 0.9)]);
+        assert!(command.candidates[0].0.parse::<Steering>().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        assert!(parse("okko").is_none());
+        assert!(parse("okko:left:token:extra").is_none());
+        assert!(parse(":left").is_none());
+        assert!(parse("okko:").is_none());
+        assert!(parse("okko:left@high").is_none());
+    }
+
+    #[test]
+    fn movements() {
+        let movement = |message: &str| parse(message).unwrap().movement();
+        let left = Steering::Absolute(Direction::Left);
+        assert_eq!(movement("okko:left"), Ok((left, Movement::Steps(1))));
+        assert_eq!(movement("okko:left 3"), Ok((left, Movement::Steps(3))));
+        assert_eq!(movement("okko:run left"), Ok((left, Movement::Run)));
+        assert_eq!(
+            movement("okko:juokse vasemmalle"),
+            Ok((left, Movement::Run))
+        );
+        assert_eq!(
+            movement("okko:turn left"),
+            Ok((Steering::TurnLeft, Movement::Steps(1)))
+        );
+        assert_eq!(
+            movement("okko:forward 2"),
+            Ok((Steering::Forward, Movement::Steps(2)))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_movements() {
+        let movement = |message: &str| parse(message).unwrap().movement();
+        assert!(movement("okko:left 0").is_err());
+        assert!(movement("okko:left x").is_err());
+        assert!(movement("okko:sideways").is_err());
+        assert!(movement("okko:run").is_err());
+        assert!(movement("okko:run left 3").is_err());
+        assert!(movement("okko:turn up").is_err());
+    }
 }
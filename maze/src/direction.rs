@@ -1,5 +1,10 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
 #[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq, Copy)]
+#[derive(Debug, Clone, PartialEq, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     Up,
     Right,
@@ -17,7 +22,7 @@ impl Direction {
             _ => Direction::Up,
         }
     }
-    pub fn to_int(&self) -> usize {
+    pub fn to_int(self) -> usize {
         match self {
             Direction::Up => 0,
             Direction::Right => 1,
@@ -33,7 +38,6 @@ impl Direction {
         }
         Direction::new(new_dir)
     }
-    #[allow(dead_code)]
     pub fn prev(&self) -> Direction {
         let dir = self.to_int();
         if dir == 0 {
@@ -57,8 +61,77 @@ impl Direction {
         self.opposite().grid_dir()
     }
 
-    #[allow(dead_code)]
     pub fn opposite(&self) -> Direction {
         self.next().next()
     }
 }
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Direction::Up),
+            "right" => Ok(Direction::Right),
+            "down" => Ok(Direction::Down),
+            "left" => Ok(Direction::Left),
+            _ => Err(format!("Invalid direction: {}", s)),
+        }
+    }
+}
+
+// What a move command asks for, relative commands are resolved against the player's heading
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Steering {
+    // In relative control mode up/down/left/right mean forward/back/turn left/turn right
+    Absolute(Direction),
+    Forward,
+    Back,
+    TurnLeft,
+    TurnRight,
+}
+
+impl Steering {
+    // Way the steering moves (or turns) to, from the heading
+    pub fn direction(self, heading: Direction) -> Direction {
+        match self {
+            Steering::Absolute(dir) => dir,
+            Steering::Forward => heading,
+            Steering::Back => heading.opposite(),
+            Steering::TurnLeft => heading.prev(),
+            Steering::TurnRight => heading.next(),
+        }
+    }
+}
+
+impl FromStr for Steering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(Steering::Forward),
+            "back" => Ok(Steering::Back),
+            "turn left" => Ok(Steering::TurnLeft),
+            "turn right" => Ok(Steering::TurnRight),
+            direction => direction
+                .parse::<Direction>()
+                .map(Steering::Absolute)
+                .map_err(|_| format!("Invalid command: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Steering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Steering::Absolute(Direction::Up) => write!(f, "up"),
+            Steering::Absolute(Direction::Right) => write!(f, "right"),
+            Steering::Absolute(Direction::Down) => write!(f, "down"),
+            Steering::Absolute(Direction::Left) => write!(f, "left"),
+            Steering::Forward => write!(f, "forward"),
+            Steering::Back => write!(f, "back"),
+            Steering::TurnLeft => write!(f, "turn left"),
+            Steering::TurnRight => write!(f, "turn right"),
+        }
+    }
+}
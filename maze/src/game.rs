@@ -1,22 +1,71 @@
-use crate::custom_events::CustomEvent;
-use crate::direction::Direction;
+use crate::bots::Bot;
+use crate::config::Config;
+use crate::coop::{describe, direction_owner, next_turn, VoteRule, Votes, TEAM_NAME};
+use crate::custom_events::{
+    is_valid_name, CommandError, ControlMode, CustomEvent, Movement, ADMIN_NAME,
+};
+use crate::direction::{Direction, Steering};
+use crate::items::{Item, CELLS_PER_ITEM};
+use crate::keyboard::LocalPlayer;
+use crate::keyword_spotting::{Enrollment, Utterance, Voices};
 use crate::maze::{Cell, MazeGrid};
+use crate::monsters::{Monster, MonsterLevel, Penalty, HUNT_RANGE, MONSTER_POINTS};
+use crate::state::{
+    CellState, CoopState, DirectionOwner, GameEvent, GameMode, GameState, MazeState, Phase,
+    PlayerState, Ranking, TeamRanking, TournamentState,
+};
+use crate::stats::{PlayerStats, Stats};
+use crate::tag::{pick_it, TAG_FREEZE};
+use crate::teams::{team_color, TeamScoring};
+use crate::tournament::Tournament;
 use crate::window::AppWindow;
 use euclid::Vector2D;
 use rand::prelude::SliceRandom;
 use rand::{thread_rng, Rng};
 use raqote::{Color, IntPoint};
+use std::cmp::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, PoisonError, RwLock, RwLockWriteGuard};
 use uuid::Uuid;
 use winit::event::Event;
 use winit_input_helper::WinitInputHelper;
 
+// Moves (and turns) remembered per player for undo
+const MAX_HISTORY: usize = 100;
+
 #[derive(Debug, Clone)]
 struct Player {
+    // Doubles as the player's session token
     id: Uuid,
     name: String,
     color: Color,
     pos: Vector2D<i32, i32>,
     size: i32,
+    // Milliseconds until the player may move again
+    move_cooldown: f64,
+    // Way the player last moved (or turned), used in relative control mode
+    heading: Direction,
+    control_mode: ControlMode,
+    // Next word to record while the player is enrolling their voice
+    enrollment: Option<Enrollment>,
+    // Position & heading before each move or turn, latest last
+    history: Vec<(Vector2D<i32, i32>, Direction)>,
+    // Moves undone this race
+    undos: u32,
+    // Milliseconds left to reconnect, while the player's connection has dropped
+    dropped: Option<f64>,
+    // Ready to start, in the lobby
+    ready: bool,
+    // Race time when the player reached the goal
+    finish_time: Option<f64>,
+    // Move commands this race
+    moves: u32,
+    team: Option<String>,
+    // Milliseconds not being it this round, in tag mode
+    free_time: f64,
+    // Items collected this race & their points
+    items: u32,
+    points: u32,
 }
 
 impl Player {
@@ -32,12 +81,33 @@ impl Player {
             size,
             pos,
             name,
+            move_cooldown: 0.,
+            heading: Direction::Right,
+            control_mode: ControlMode::Absolute,
+            enrollment: None,
+            history: vec![],
+            undos: 0,
+            dropped: None,
+            ready: false,
+            finish_time: None,
+            moves: 0,
+            team: None,
+            free_time: 0.,
+            items: 0,
+            points: 0,
         }
     }
 
     pub fn move_to(&mut self, cell: &Cell) {
         self.pos = cell.pos()
     }
+
+    fn remember(&mut self) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push((self.pos, self.heading));
+    }
 }
 
 pub struct MazeGame {
@@ -47,173 +117,1713 @@ pub struct MazeGame {
     cell_size: i32,
     players: Vec<Player>,
     wall_padding: i32,
-    is_finished: bool,
+    phase: Phase,
+    // Milliseconds left of the countdown
+    countdown: f64,
     winner: Option<String>,
+    subscribers: Vec<Sender<GameEvent>>,
+    // Minimum milliseconds between a player's accepted moves
+    move_interval: f64,
+    // Recognized moves less confident than this are rejected
+    min_confidence: f32,
+    voices: Arc<RwLock<Voices>>,
+    local_players: Vec<LocalPlayer>,
+    undo_limit: Option<u32>,
+    undo_penalty: f64,
+    // Moves are rejected while an admin has paused the game
+    paused: bool,
+    window_size: (u32, u32),
+    reconnect_grace: f64,
+    countdown_length: f64,
+    no_lobby: bool,
+    // Milliseconds since the race started, stopped while paused
+    race_time: f64,
+    time_limit: Option<f64>,
+    stats: Stats,
+    tournament: Option<Tournament>,
+    mode: GameMode,
+    vote_rule: VoteRule,
+    vote_window: f64,
+    votes: Votes,
+    // Whose move it is, with the turns vote rule
+    turn: Option<String>,
+    team_scoring: TeamScoring,
+    // Player chasing the others, in tag mode
+    it: Option<String>,
+    // Items on each maze, None for the mode's default
+    items: Option<usize>,
+    bots: Vec<Bot>,
+    // Milliseconds between a bot's moves
+    bot_interval: f64,
+    monsters: Vec<Monster>,
+    // Monsters on each maze
+    monster_count: usize,
+    monster_level: MonsterLevel,
+    monster_penalty: Penalty,
 }
 
 impl MazeGame {
-    pub fn new(grid_size: i32, window: &AppWindow) -> MazeGame {
-        let maze = MazeGrid::new(grid_size, (0, 0), (grid_size - 1, grid_size - 1));
-        let (buffer_width, buffer_height) = window.size();
-        let wall_padding = 2;
-        // Just some math to get the grid fit height of window
-        let cell_size = ((window.size().1 as f32 - 1.05 * grid_size as f32 * wall_padding as f32)
-            / (1.05 * grid_size as f32)) as i32;
+    pub fn new(config: &Config, window: &AppWindow) -> MazeGame {
+        MazeGame::with_window_size(config, window.size())
+    }
+
+    fn with_window_size(config: &Config, window_size: (u32, u32)) -> MazeGame {
+        let grid_size = config.maze_size;
+        let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+        let maze = MazeGrid::new(grid_size, (0, 0), (grid_size - 1, grid_size - 1), seed);
         let input = WinitInputHelper::new();
         let players = vec![];
-        MazeGame {
+        let mut game = MazeGame {
             maze,
-            camera_pos: IntPoint::new(
-                buffer_width as i32 / 2 - grid_size as i32 / 2 * cell_size,
-                buffer_height as i32 / 2 - grid_size as i32 / 2 * cell_size,
-            ),
+            camera_pos: IntPoint::new(0, 0),
             input,
-            cell_size,
+            cell_size: 0,
             players,
-            wall_padding,
-            is_finished: false,
+            wall_padding: 2,
+            phase: if config.no_lobby {
+                Phase::Playing
+            } else {
+                Phase::Lobby
+            },
+            countdown: 0.,
             winner: None,
+            subscribers: vec![],
+            move_interval: config.move_interval,
+            min_confidence: config.min_confidence,
+            voices: config.voices.clone(),
+            local_players: config.local_players.clone(),
+            undo_limit: config.undo_limit,
+            undo_penalty: config.undo_penalty,
+            paused: false,
+            window_size,
+            reconnect_grace: config.reconnect_grace,
+            countdown_length: config.countdown,
+            no_lobby: config.no_lobby,
+            race_time: 0.,
+            time_limit: config.time_limit,
+            stats: config.stats.clone(),
+            tournament: config.tournament.clone(),
+            mode: config.mode,
+            vote_rule: config.vote_rule,
+            vote_window: config.vote_window,
+            votes: Votes::default(),
+            turn: None,
+            team_scoring: config.team_scoring,
+            it: None,
+            items: config.items,
+            bots: vec![],
+            bot_interval: config.bot_interval,
+            monsters: vec![],
+            monster_count: config.monsters,
+            monster_level: config.monster_level,
+            monster_penalty: config.monster_penalty.unwrap_or(match config.mode {
+                GameMode::Score => Penalty::Points,
+                _ => Penalty::Start,
+            }),
+        };
+        game.maze.place_items(game.item_count());
+        game.monsters = Monster::spawn(&game.maze, game.monster_count);
+        game.layout();
+        if game.mode == GameMode::Coop {
+            let mut team = Player::new(
+                game.cell_size / 2,
+                game.maze.start_pos(),
+                TEAM_NAME.to_string(),
+            );
+            team.color = Color::new(255, 255, 255, 0);
+            game.players.push(team);
+        }
+        for local_player in config.local_players.iter() {
+            game.add_player(&local_player.name).ok();
+        }
+        for bot in config.bots.iter() {
+            if let Err(e) = game.add_bot(bot.clone()) {
+                println!("Bot not added: {}", e);
+            }
         }
+        game
     }
 
-    pub fn restart(&mut self) {
-        let maze = MazeGrid::new(
-            self.maze.size(),
-            (0, 0),
-            (self.maze.size() - 1, self.maze.size() - 1),
+    // Sizes cells & centers the grid so the maze fits the window
+    fn layout(&mut self) {
+        let grid_size = self.maze.size();
+        let (buffer_width, buffer_height) = self.window_size;
+        // Just some math to get the grid fit height of window
+        self.cell_size = ((buffer_height as f32
+            - 1.05 * grid_size as f32 * self.wall_padding as f32)
+            / (1.05 * grid_size as f32)) as i32;
+        self.camera_pos = IntPoint::new(
+            buffer_width as i32 / 2 - grid_size / 2 * self.cell_size,
+            buffer_height as i32 / 2 - grid_size / 2 * self.cell_size,
         );
-        let mut players = vec![];
-        for p in &self.players {
-            let mut player = p.clone();
-            player.pos = Vector2D::<i32, i32>::new(0, 0);
-            players.push(player);
-        }
-        self.maze = maze;
-        self.players = players;
-        self.is_finished = false;
+        for player in self.players.iter_mut() {
+            player.size = self.cell_size / 2;
+        }
+    }
+
+    // In a tournament, a restart after the race moves on to the next round
+    pub fn restart(&mut self) {
+        let size = match self.tournament.as_mut() {
+            Some(tournament) if self.phase == Phase::Finished => {
+                tournament.next_round();
+                tournament.maze_size()
+            }
+            _ => self.maze.size(),
+        };
+        self.regenerate(size);
+    }
+
+    // New maze of the given size, everyone back to start
+    pub fn regenerate(&mut self, size: i32) {
+        let seed = thread_rng().gen();
+        self.maze = MazeGrid::new(size, (0, 0), (size - 1, size - 1), seed);
+        self.maze.place_items(self.item_count());
+        self.monsters = Monster::spawn(&self.maze, self.monster_count);
+        self.layout();
+        let start = self.maze.start_pos();
+        for player in self.players.iter_mut() {
+            player.pos = start;
+            player.heading = Direction::Right;
+            player.history.clear();
+            player.undos = 0;
+            player.ready = false;
+            player.finish_time = None;
+            player.moves = 0;
+            player.free_time = 0.;
+            player.items = 0;
+            player.points = 0;
+        }
         self.winner = None;
+        self.it = None;
+        self.race_time = 0.;
+        self.votes.clear();
+        self.turn = None;
+        self.broadcast(GameEvent::MazeRegenerated {
+            maze: self.maze_state(),
+        });
+        if self.no_lobby {
+            self.set_phase(Phase::Playing);
+        } else {
+            self.set_phase(Phase::Lobby);
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Seconds left of the countdown, rounded up
+    pub fn countdown_seconds(&self) -> u64 {
+        (self.countdown / 1000.).ceil() as u64
+    }
+
+    fn set_phase(&mut self, phase: Phase) {
+        if self.phase != phase {
+            self.phase = phase;
+            self.broadcast(GameEvent::PhaseChanged { phase });
+            println!("Game phase: {:?}", phase);
+        }
+    }
+
+    pub fn set_ready(&mut self, name: &str) -> Result<(), CommandError> {
+        self.find_player(name)?;
+        if self.phase != Phase::Lobby {
+            return Err(CommandError::NotPlaying(self.phase));
+        }
+        let player = self.get_player(name);
+        if !player.ready {
+            player.ready = true;
+            self.broadcast(GameEvent::PlayerReady {
+                name: name.to_string(),
+            });
+        }
+        self.start_if_ready();
+        Ok(())
+    }
+
+    // Counts down once every connected player is ready, bots wait for at least one human
+    fn start_if_ready(&mut self) {
+        let connected = self
+            .players
+            .iter()
+            .filter(|p| p.dropped.is_none() && !self.is_team(p) && !self.is_bot(&p.name));
+        let mut all_ready = true;
+        let mut any = false;
+        for player in connected {
+            any = true;
+            all_ready &= player.ready;
+        }
+        if self.phase == Phase::Lobby && any && all_ready {
+            self.start_countdown();
+        }
+    }
+
+    pub fn start_countdown(&mut self) {
+        if self.phase == Phase::Lobby {
+            self.countdown = self.countdown_length;
+            self.set_phase(Phase::Countdown);
+        }
+    }
+
+    // Race ends once every connected player (or the team, in coop mode) has finished
+    fn finish_if_done(&mut self) {
+        let racers = self.players.iter().filter(|p| self.is_racer(p));
+        let mut all_finished = true;
+        let mut any = false;
+        for player in racers {
+            any = true;
+            all_finished &= player.finish_time.is_some() || self.team_finished(player);
+        }
+        if self.phase == Phase::Playing && any && all_finished {
+            self.finish_race();
+        }
+    }
+
+    fn finish_race(&mut self) {
+        self.set_phase(Phase::Finished);
+        let rankings = self.rankings();
+        // Tag & score attack are won by the best score
+        if self.mode == GameMode::Tag || self.mode == GameMode::Score {
+            self.winner = rankings.first().map(|r| r.name.clone());
+        }
+        // Only people make the leaderboard & tournament standings, not bots or the coop team,
+        // so places are counted among people
+        let people = rankings
+            .iter()
+            .filter(|r| {
+                !(self.is_bot(&r.name) || self.mode == GameMode::Coop && r.name == TEAM_NAME)
+            })
+            .enumerate()
+            .map(|(i, r)| Ranking {
+                place: i as u32 + 1,
+                ..r.clone()
+            })
+            .collect::<Vec<Ranking>>();
+        self.stats
+            .record(&people, self.maze.size(), self.maze.seed());
+        if let Some(tournament) = self.tournament.as_mut() {
+            tournament.score(&people);
+        }
+        self.broadcast(GameEvent::RaceFinished {
+            rankings,
+            teams: self.team_rankings(),
+        });
+        if let Some(state) = self.tournament().filter(|t| t.over) {
+            let standings = state.standings;
+            if let Some(standing) = standings.first() {
+                println!(
+                    "Tournament won by {} with {} points",
+                    standing.name, standing.points
+                );
+            }
+            self.broadcast(GameEvent::TournamentFinished { standings });
+        }
+    }
+
+    pub fn tournament(&self) -> Option<TournamentState> {
+        self.tournament.as_ref().map(|t| t.state())
+    }
+
+    // Players of all sessions, best first
+    pub fn leaderboard(&self) -> Vec<PlayerStats> {
+        self.stats.leaderboard()
+    }
+
+    // Finished by the team scoring, e.g. when a teammate reaches the goal with the first scoring
+    fn team_finished(&self, player: &Player) -> bool {
+        match &player.team {
+            Some(team) => self
+                .team_rankings()
+                .iter()
+                .any(|t| t.name == *team && t.time.is_some()),
+            None => false,
+        }
+    }
+
+    // Teams that have reached the goal by time, then the rest by how many members have
+    pub fn team_rankings(&self) -> Vec<TeamRanking> {
+        let mut teams: Vec<(TeamRanking, Vec<Option<f64>>)> = vec![];
+        for player in self.players.iter() {
+            let team = match &player.team {
+                Some(team) => team,
+                None => continue,
+            };
+            let index = match teams.iter().position(|(t, _)| t.name == *team) {
+                Some(index) => index,
+                None => {
+                    let color = team_color(team);
+                    teams.push((
+                        TeamRanking {
+                            place: 0,
+                            name: team.clone(),
+                            color: [color.r(), color.g(), color.b()],
+                            members: vec![],
+                            finished: 0,
+                            time: None,
+                        },
+                        vec![],
+                    ));
+                    teams.len() - 1
+                }
+            };
+            let (ranking, times) = &mut teams[index];
+            ranking.members.push(player.name.clone());
+            if player.finish_time.is_some() {
+                ranking.finished += 1;
+            }
+            times.push(player.finish_time);
+        }
+        let mut rankings = teams
+            .into_iter()
+            .map(|(mut ranking, times)| {
+                let finished = times.iter().flatten().map(|time| *time as u64);
+                ranking.time = match self.team_scoring {
+                    TeamScoring::All if ranking.finished as usize == times.len() => finished.max(),
+                    TeamScoring::All => None,
+                    TeamScoring::First => finished.min(),
+                };
+                ranking
+            })
+            .collect::<Vec<TeamRanking>>();
+        rankings.sort_by(|a, b| match (a.time, b.time) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => b.finished.cmp(&a.finished).then(a.name.cmp(&b.name)),
+        });
+        for (i, ranking) in rankings.iter_mut().enumerate() {
+            ranking.place = i as u32 + 1;
+        }
+        rankings
+    }
+
+    // Joins a team (taking its color), or leaves it with None
+    pub fn set_team(&mut self, name: &str, team: Option<String>) -> Result<(), CommandError> {
+        self.find_player(name)?;
+        let player = self.get_player(name);
+        if let Some(team) = &team {
+            player.color = team_color(team);
+        }
+        player.team = team.clone();
+        println!("Player team: {} {:?}", name, team);
+        self.broadcast(GameEvent::PlayerJoinedTeam {
+            name: name.to_string(),
+            team,
+        });
+        Ok(())
+    }
+
+    // The team's avatar in coop mode
+    fn is_team(&self, player: &Player) -> bool {
+        self.mode == GameMode::Coop && player.name == TEAM_NAME
+    }
+
+    // Whoever the race is between: the team in coop mode, otherwise the connected players
+    fn is_racer(&self, player: &Player) -> bool {
+        match self.mode {
+            GameMode::Coop => self.is_team(player),
+            GameMode::Race | GameMode::Tag | GameMode::Score => player.dropped.is_none(),
+        }
+    }
+
+    // Only the team's avatar is on the board in coop mode
+    fn is_on_board(&self, player: &Player) -> bool {
+        self.mode != GameMode::Coop || self.is_team(player)
+    }
+
+    // Monsters move on a timer, wandering along walls (or after nearby players when hard)
+    fn move_monsters(&mut self, dt: f64) {
+        let level = self.monster_level;
+        for i in 0..self.monsters.len() {
+            if !self.monsters[i].tick(dt, level.interval()) {
+                continue;
+            }
+            let pos = self.monsters[i].pos;
+            let prey = if level.hunts() {
+                let nearby = self
+                    .players
+                    .iter()
+                    .filter(|p| self.is_catchable(p))
+                    .filter(|p| (p.pos.x - pos.x).abs() + (p.pos.y - pos.y).abs() <= HUNT_RANGE)
+                    .map(|p| p.pos)
+                    .collect::<Vec<Vector2D<i32, i32>>>();
+                self.maze.first_step(pos, &nearby)
+            } else {
+                None
+            };
+            self.monsters[i].step(&self.maze, prey);
+            let pos = self.monsters[i].pos;
+            self.broadcast(GameEvent::MonsterMoved {
+                monster: i,
+                x: pos.x,
+                y: pos.y,
+            });
+            self.monster_catch(pos);
+        }
+    }
+
+    // Still racing & on the board
+    fn is_catchable(&self, player: &Player) -> bool {
+        self.is_on_board(player) && player.dropped.is_none() && player.finish_time.is_none()
+    }
+
+    // Players in a monster's cell get the penalty
+    fn monster_catch(&mut self, pos: Vector2D<i32, i32>) {
+        if !self.monsters.iter().any(|m| m.pos == pos) {
+            return;
+        }
+        let caught = self
+            .players
+            .iter()
+            .filter(|p| p.pos == pos && self.is_catchable(p))
+            .map(|p| p.name.clone())
+            .collect::<Vec<String>>();
+        let (penalty, start) = (self.monster_penalty, self.maze.start_pos());
+        for name in caught {
+            let player = self.get_player(&name);
+            let points_lost = match penalty {
+                Penalty::Start => {
+                    player.pos = start;
+                    player.heading = Direction::Right;
+                    player.history.clear();
+                    0
+                }
+                Penalty::Points => {
+                    let points_lost = player.points.min(MONSTER_POINTS);
+                    player.points -= points_lost;
+                    points_lost
+                }
+            };
+            let pos = player.pos;
+            self.broadcast(GameEvent::PlayerCaught {
+                name: name.clone(),
+                x: pos.x,
+                y: pos.y,
+                points_lost,
+            });
+            println!("Player caught by a monster: {}", name);
+        }
+    }
+
+    // One item per few cells in score attack, unless given
+    fn item_count(&self) -> usize {
+        self.items.unwrap_or(match self.mode {
+            GameMode::Score => (self.maze.size() * self.maze.size() / CELLS_PER_ITEM) as usize,
+            _ => 0,
+        })
+    }
+
+    // Player picks up items of the cells they passed, ends score attack once all are collected
+    fn collect_items(&mut self, player: &str, cells: &[Vector2D<i32, i32>]) {
+        let mut collected = false;
+        for pos in cells.iter() {
+            if let Some(item) = self.maze.take_item(*pos) {
+                collected = true;
+                let p = self.get_player(player);
+                p.items += 1;
+                p.points += item.points();
+                self.broadcast(GameEvent::ItemCollected {
+                    name: player.to_string(),
+                    item,
+                    x: pos.x,
+                    y: pos.y,
+                });
+                println!("Player collected: {} {:?}", player, item);
+            }
+        }
+        if collected && self.mode == GameMode::Score && self.maze.items().is_empty() {
+            println!("All items collected");
+            self.finish_race();
+        }
+    }
+
+    // Keeps someone it while anyone is playing, e.g. the first one when the round starts
+    // or another one when it leaves
+    fn check_it(&mut self) {
+        let players = self
+            .players
+            .iter()
+            .filter(|p| p.dropped.is_none())
+            .map(|p| p.name.clone())
+            .collect::<Vec<String>>();
+        if self.it.as_ref().is_some_and(|it| players.contains(it)) {
+            return;
+        }
+        self.it = None;
+        if let Some(name) = pick_it(&players) {
+            self.set_it(&name, None);
+        }
+    }
+
+    // New it waits a moment before chasing
+    fn set_it(&mut self, name: &str, by: Option<&str>) {
+        self.it = Some(name.to_string());
+        self.get_player(name).move_cooldown = TAG_FREEZE;
+        self.broadcast(GameEvent::PlayerTagged {
+            name: name.to_string(),
+            by: by.map(|by| by.to_string()),
+        });
+        match by {
+            Some(by) => println!("Player tagged: {} by {}", name, by),
+            None => println!("Player is it: {}", name),
+        }
     }
 
-    pub fn players(&self) -> Vec<(String, Color)> {
+    // It catches whoever is in the cell it landed on
+    fn catch(&mut self, hunter: &str, pos: Vector2D<i32, i32>) {
+        if self.it.as_deref() != Some(hunter) {
+            return;
+        }
+        let caught = self
+            .players
+            .iter()
+            .find(|p| p.pos == pos && p.name != hunter && p.dropped.is_none())
+            .map(|p| p.name.clone());
+        if let Some(name) = caught {
+            self.set_it(&name, Some(hunter));
+        }
+    }
+
+    // Finishers by time, then the rest by how close they got to the goal, in cells counting rows
+    // & columns (Manhattan distance, walls aside).
+    // In tag mode by time not being it, in score attack by points.
+    pub fn rankings(&self) -> Vec<Ranking> {
+        let end = self.maze.end_pos();
         let mut players = self
             .players
             .iter()
-            .map(|p| (p.name.clone(), p.color))
-            .collect::<Vec<(String, Color)>>();
-        players.sort_by(|a, b| a.0.cmp(&b.0));
+            .filter(|p| self.is_on_board(p))
+            .collect::<Vec<&Player>>();
+        players.sort_by(|a, b| match (a.finish_time, b.finish_time) {
+            _ if self.mode == GameMode::Tag => b
+                .free_time
+                .partial_cmp(&a.free_time)
+                .unwrap_or(Ordering::Equal)
+                .then(a.name.cmp(&b.name)),
+            _ if self.mode == GameMode::Score => b.points.cmp(&a.points).then(a.name.cmp(&b.name)),
+            (Some(time_a), Some(time_b)) => time_a
+                .partial_cmp(&time_b)
+                .unwrap_or(Ordering::Equal)
+                .then(a.name.cmp(&b.name)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => {
+                let distance = |p: &Player| (end.x - p.pos.x).abs() + (end.y - p.pos.y).abs();
+                distance(a).cmp(&distance(b)).then(a.name.cmp(&b.name))
+            }
+        });
         players
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Ranking {
+                place: i as u32 + 1,
+                name: p.name.clone(),
+                time: p.finish_time.map(|time| time as u64),
+                moves: p.moves,
+                score: match self.mode {
+                    GameMode::Tag => Some(p.free_time as u64),
+                    GameMode::Score => Some(p.points as u64),
+                    _ => None,
+                },
+            })
+            .collect()
+    }
+
+    pub fn race_time(&self) -> f64 {
+        self.race_time
+    }
+
+    // Moves & undos are only accepted while the race is on
+    fn check_playing(&self) -> Result<(), CommandError> {
+        if self.paused {
+            Err(CommandError::Paused)
+        } else if self.phase != Phase::Playing {
+            Err(CommandError::NotPlaying(self.phase))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Returns the new player's session token
+    fn add_player(&mut self, name: &str) -> Result<String, CommandError> {
+        if !is_valid_name(name) {
+            return Err(CommandError::InvalidName(name.to_string()));
+        }
+        if name == ADMIN_NAME || name == TEAM_NAME || self.players.iter().any(|p| p.name == name) {
+            return Err(CommandError::NameTaken(name.to_string()));
+        }
+        let start = self.maze.start_pos();
+        let player = Player::new(self.cell_size / 2, start, name.to_string());
+        let token = player.id.to_string();
+        self.players.push(player);
+        self.broadcast(GameEvent::PlayerJoined {
+            name: name.to_string(),
+            x: start.x,
+            y: start.y,
+        });
+        Ok(token)
     }
 
-    fn add_player(&mut self, name: &str) {
-        if self.players.iter().find(|p| &p.name == name).is_none() {
-            self.players.push(Player::new(
-                self.cell_size / 2,
-                self.maze.start_pos(),
-                name.to_string(),
-            ));
+    // Bot joins like any player, but is moved by the game itself
+    pub fn add_bot(&mut self, bot: Bot) -> Result<(), CommandError> {
+        self.add_player(&bot.name)?;
+        println!("Bot joined: {} {:?}", bot.name, bot.level);
+        self.bots.push(bot);
+        Ok(())
+    }
+
+    fn is_bot(&self, name: &str) -> bool {
+        self.bots.iter().any(|b| b.name == name)
+    }
+
+    // Bots ready up right away in the lobby
+    fn ready_bots(&mut self) {
+        let unready = self
+            .bots
+            .iter()
+            .filter(|b| self.players.iter().any(|p| p.name == b.name && !p.ready))
+            .map(|b| b.name.clone())
+            .collect::<Vec<String>>();
+        for name in unready {
+            self.set_ready(&name).ok();
+        }
+    }
+
+    // Bots move on their own timers, towards the goal (or items, or whoever they chase as it)
+    fn move_bots(&mut self, dt: f64) {
+        let interval = self.bot_interval;
+        for i in 0..self.bots.len() {
+            if !self.bots[i].tick(dt, interval) {
+                continue;
+            }
+            let name = self.bots[i].name.clone();
+            // In coop mode bots vote for the team's moves
+            let mover = match self.mode {
+                GameMode::Coop => TEAM_NAME,
+                _ => &name,
+            };
+            let (pos, heading) = match self.players.iter().find(|p| p.name == mover) {
+                Some(p) if p.finish_time.is_none() => (p.pos, p.heading),
+                _ => continue,
+            };
+            let targets = self.bot_targets(&name);
+            let dir = self.bots[i].next_move(&self.maze, pos, heading, &targets);
+            if let Some(dir) = dir {
+                let movement = Movement::Steps(1);
+                if self.steer(&name, Steering::Absolute(dir), movement).is_ok() {
+                    println!("Player move: {} {:?} {:?}", name, dir, movement);
+                }
+            }
+        }
+    }
+
+    // Where a bot is headed: the goal, items in score attack, the others when it's it in tag
+    fn bot_targets(&self, bot: &str) -> Vec<Vector2D<i32, i32>> {
+        match self.mode {
+            GameMode::Score => self.maze.items(),
+            GameMode::Tag if self.it.as_deref() == Some(bot) => self
+                .players
+                .iter()
+                .filter(|p| p.name != bot && p.dropped.is_none())
+                .map(|p| p.pos)
+                .collect(),
+            // Others just run around
+            GameMode::Tag => vec![],
+            GameMode::Race | GameMode::Coop => vec![self.maze.end_pos()],
         }
     }
 
+    // Players currently enrolling, with the word they should say next
+    pub fn enrollments(&self) -> Vec<(String, String)> {
+        self.players
+            .iter()
+            .filter_map(|p| Some((p.name.clone(), p.enrollment?.prompt())))
+            .collect()
+    }
+
+    // Starts recording player's own voice templates over, returns the first word to say
+    pub fn start_enrollment(&mut self, name: &str) -> Result<String, CommandError> {
+        self.find_player(name)?;
+        self.voices().start_enrollment(name);
+        let enrollment = Enrollment::default();
+        self.get_player(name).enrollment = Some(enrollment);
+        println!("Player enrolling: {}", name);
+        Ok(enrollment.prompt())
+    }
+
+    // Stores the recording as a template when enrolling, otherwise moves by the recognized command
+    fn handle_audio(&mut self, name: &str, utterance: &Utterance) -> Result<String, CommandError> {
+        match (self.get_player(name).enrollment, utterance) {
+            (Some(enrollment), Utterance::Template(recording)) => {
+                self.voices()
+                    .enroll(name, enrollment.word(), recording)
+                    .map_err(CommandError::Unrecognized)?;
+                let next = enrollment.next();
+                if next.is_none() {
+                    self.voices().finish_enrollment(name);
+                }
+                self.get_player(name).enrollment = next;
+                Ok(next.map_or("done".to_string(), |e| e.prompt()))
+            }
+            (None, Utterance::Candidates(candidates)) => {
+                let candidates = candidates.clone().map_err(CommandError::Unrecognized)?;
+                let (steps, steering) = self.steer_best_guess(name, &candidates)?;
+                println!("Player move: {} {:?} from {:?}", name, steering, candidates);
+                Ok(format!("{} {}", steps, steering))
+            }
+            // Enrollment started (or ended) while the recording was on its way
+            _ => Err(CommandError::Unrecognized(
+                "Enrollment changed, record again".to_string(),
+            )),
+        }
+    }
+
+    fn voices(&self) -> RwLockWriteGuard<'_, Voices> {
+        self.voices.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Only the one holding the player's session token may control it
+    fn check_token(&self, name: &str, token: &str) -> Result<(), CommandError> {
+        match self.players.iter().find(|p| p.name == name) {
+            Some(player) if player.id.to_string() == token => Ok(()),
+            Some(_) => Err(CommandError::Unauthorized),
+            None => Err(CommandError::UnknownPlayer(name.to_string())),
+        }
+    }
+
+    // A command of a dropped player (e.g. from a one-shot connection) shows they're still around,
+    // so it reclaims the player like a reconnect would
+    fn authorize(&mut self, name: &str, token: &str) -> Result<(), CommandError> {
+        self.check_token(name, token)?;
+        if self.get_player(name).dropped.is_some() {
+            self.reconnect(token)?;
+            println!("Player reconnected: {}", name);
+        }
+        Ok(())
+    }
+
     fn remove_player(&mut self, name: &str) {
-        if self.players.iter().find(|p| &p.name == name).is_some() {
-            let index = self.players.iter().position(|p| &p.name == name).unwrap();
+        if let Some(index) = self.players.iter().position(|p| p.name == name) {
             self.players.remove(index);
+            self.votes.forget(name);
+            self.voices().cancel_enrollment(name);
+            self.bots.retain(|b| b.name != name);
+            self.broadcast(GameEvent::PlayerLeft {
+                name: name.to_string(),
+            });
+            // Whoever was still not ready (or finished) may have left
+            self.start_if_ready();
+            self.finish_if_done();
+        }
+    }
+
+    // Keeps the player, greyed out, until they reconnect or the grace period runs out
+    fn drop_player(&mut self, name: &str) {
+        let reconnect_grace = self.reconnect_grace;
+        self.get_player(name).dropped = Some(reconnect_grace);
+        self.broadcast(GameEvent::PlayerDropped {
+            name: name.to_string(),
+        });
+        self.start_if_ready();
+        self.finish_if_done();
+    }
+
+    // Returns the name of the player the token belongs to
+    fn reconnect(&mut self, token: &str) -> Result<String, CommandError> {
+        let player = self
+            .players
+            .iter_mut()
+            .find(|p| p.id.to_string() == token)
+            .ok_or(CommandError::Unauthorized)?;
+        player.dropped = None;
+        let name = player.name.clone();
+        self.broadcast(GameEvent::PlayerReconnected { name: name.clone() });
+        Ok(name)
+    }
+
+    fn find_player(&self, name: &str) -> Result<(), CommandError> {
+        if self.players.iter().any(|p| p.name == name) {
+            Ok(())
+        } else {
+            Err(CommandError::UnknownPlayer(name.to_string()))
+        }
+    }
+
+    pub fn kick(&mut self, name: &str) -> Result<(), CommandError> {
+        self.find_player(name)?;
+        if name == TEAM_NAME {
+            return Err(CommandError::Unauthorized);
+        }
+        self.remove_player(name);
+        println!("Player kicked: {}", name);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), CommandError> {
+        self.find_player(name)?;
+        if name == TEAM_NAME {
+            return Err(CommandError::Unauthorized);
+        }
+        if !is_valid_name(new_name) {
+            return Err(CommandError::InvalidName(new_name.to_string()));
+        }
+        if new_name == ADMIN_NAME
+            || new_name == TEAM_NAME
+            || self.players.iter().any(|p| p.name == new_name)
+        {
+            return Err(CommandError::NameTaken(new_name.to_string()));
+        }
+        self.votes.rename(name, new_name);
+        if self.turn.as_deref() == Some(name) {
+            self.turn = Some(new_name.to_string());
+        }
+        self.get_player(name).name = new_name.to_string();
+        self.voices().rename(name, new_name);
+        for local_player in self.local_players.iter_mut() {
+            if local_player.name == name {
+                local_player.name = new_name.to_string();
+            }
+        }
+        for bot in self.bots.iter_mut().filter(|b| b.name == name) {
+            bot.name = new_name.to_string();
+        }
+        if let Some(tournament) = self.tournament.as_mut() {
+            tournament.rename(name, new_name);
+        }
+        if self.winner.as_deref() == Some(name) {
+            self.winner = Some(new_name.to_string());
+        }
+        if self.it.as_deref() == Some(name) {
+            self.it = Some(new_name.to_string());
+        }
+        self.broadcast(GameEvent::PlayerRenamed {
+            name: name.to_string(),
+            new_name: new_name.to_string(),
+        });
+        println!("Player renamed: {} -> {}", name, new_name);
+        Ok(())
+    }
+
+    // Sends player back to start, e.g. when they got stuck
+    pub fn reset_player(&mut self, name: &str) -> Result<(), CommandError> {
+        self.find_player(name)?;
+        let start = self.maze.start_pos();
+        let player = self.get_player(name);
+        player.pos = start;
+        player.heading = Direction::Right;
+        player.history.clear();
+        self.broadcast(GameEvent::PlayerReset {
+            name: name.to_string(),
+            x: start.x,
+            y: start.y,
+        });
+        println!("Player sent to start: {}", name);
+        Ok(())
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.broadcast(if paused {
+                GameEvent::GamePaused
+            } else {
+                GameEvent::GameResumed
+            });
+            println!("Game {}", if paused { "paused" } else { "resumed" });
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn maze_size(&self) -> i32 {
+        self.maze.size()
+    }
+
+    pub fn state(&self) -> GameState {
+        GameState {
+            mode: self.mode,
+            phase: self.phase,
+            countdown: if self.phase == Phase::Countdown {
+                Some(self.countdown.ceil() as u64)
+            } else {
+                None
+            },
+            finished: self.phase == Phase::Finished,
+            race_time: self.race_time as u64,
+            time_limit: self.time_limit.map(|ms| ms as u64),
+            rankings: self.rankings(),
+            team_scoring: self.team_scoring,
+            teams: self.team_rankings(),
+            tournament: self.tournament(),
+            coop: self.coop_state(),
+            it: self.it.clone(),
+            monsters: self.monsters.iter().map(|m| [m.pos.x, m.pos.y]).collect(),
+            winner: self.winner.clone(),
+            paused: self.paused,
+            players: self
+                .players
+                .iter()
+                .map(|p| PlayerState {
+                    name: p.name.clone(),
+                    color: [p.color.r(), p.color.g(), p.color.b()],
+                    x: p.pos.x,
+                    y: p.pos.y,
+                    heading: p.heading,
+                    control_mode: p.control_mode,
+                    undos: p.undos,
+                    connected: p.dropped.is_none(),
+                    bot: self.bots.iter().find(|b| b.name == p.name).map(|b| b.level),
+                    ready: p.ready,
+                    finish_time: p.finish_time.map(|time| time as u64),
+                    moves: p.moves,
+                    team: p.team.clone(),
+                    free_time: p.free_time as u64,
+                    items: p.items,
+                    points: p.points,
+                })
+                .collect(),
+            undo_limit: self.undo_limit,
+        }
+    }
+
+    pub fn coop_state(&self) -> Option<CoopState> {
+        if self.mode != GameMode::Coop {
+            return None;
+        }
+        let voters = self.voters();
+        let owners = match self.vote_rule {
+            VoteRule::Directions => (0..4)
+                .filter_map(|d| {
+                    let direction = Direction::new(d);
+                    Some(DirectionOwner {
+                        direction,
+                        name: direction_owner(&voters, direction)?.clone(),
+                    })
+                })
+                .collect(),
+            _ => vec![],
+        };
+        Some(CoopState {
+            rule: self.vote_rule,
+            turn: match self.vote_rule {
+                VoteRule::Turns => self.current_turn(&voters),
+                _ => None,
+            },
+            votes: self.votes.list(),
+            owners,
+        })
+    }
+
+    pub fn maze_state(&self) -> MazeState {
+        let mut cells = vec![];
+        for y in 0..self.maze.size() {
+            for x in 0..self.maze.size() {
+                if let Some(cell) = self.maze.cell_at(x, y) {
+                    cells.push(CellState {
+                        x,
+                        y,
+                        directions: cell.available_directions().clone(),
+                        item: cell.item(),
+                    });
+                }
+            }
+        }
+        MazeState {
+            size: self.maze.size(),
+            seed: self.maze.seed(),
+            start: [self.maze.start_pos().x, self.maze.start_pos().y],
+            end: [self.maze.end_pos().x, self.maze.end_pos().y],
+            cells,
         }
     }
 
     pub fn handle_custom_events(&mut self, event: &Event<CustomEvent>) {
-        match event {
-            Event::UserEvent(event) => match event {
-                CustomEvent::PlayerConnected(name) => {
-                    self.add_player(name);
-                    println!("Player connected: {}", name);
-                }
-                CustomEvent::PlayerDisconnected(name) => {
-                    self.remove_player(name);
-                    println!("Player disconnected: {}", name);
-                }
-                CustomEvent::PlayerMove(name, direction) => {
-                    if self.players.iter().find(|p| &p.name == name).is_none() {
-                        self.add_player(name);
+        if let Event::UserEvent(event) = event {
+            match event {
+                CustomEvent::PlayerConnected(name, reply) => {
+                    let result = self.add_player(name);
+                    if result.is_ok() {
+                        println!("Player connected: {}", name);
                     }
-                    self.try_move(name, *direction);
-                    println!("Player move: {} {:?}", name, direction);
+                    reply.send(result).ok();
                 }
-            },
-            _ => (),
+                CustomEvent::PlayerDisconnected(name, token, reply) => {
+                    let result = self.authorize(name, token);
+                    if result.is_ok() {
+                        self.remove_player(name);
+                        println!("Player disconnected: {}", name);
+                    }
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::PlayerDropped(name, token) => {
+                    if self.check_token(name, token).is_ok() {
+                        self.drop_player(name);
+                        println!("Player dropped: {}", name);
+                    }
+                }
+                CustomEvent::PlayerReconnected(token, reply) => {
+                    let result = self.reconnect(token);
+                    if let Ok(name) = &result {
+                        println!("Player reconnected: {}", name);
+                    }
+                    reply.send(result).ok();
+                }
+                CustomEvent::PlayerMove(name, token, steering, movement, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.steer(name, *steering, *movement));
+                    if result.is_ok() {
+                        println!("Player move: {} {:?} {:?}", name, steering, movement);
+                    }
+                    reply.send(result.map(|steps| steps.to_string())).ok();
+                }
+                CustomEvent::PlayerMoveGuess(name, token, candidates, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.steer_best_guess(name, candidates));
+                    if let Ok((_, steering)) = &result {
+                        println!("Player move: {} {:?} from {:?}", name, steering, candidates);
+                    }
+                    let result = result.map(|(steps, steering)| format!("{} {}", steps, steering));
+                    reply.send(result).ok();
+                }
+                CustomEvent::PlayerAudio(name, token, utterance, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.handle_audio(name, utterance));
+                    reply.send(result).ok();
+                }
+                CustomEvent::PlayerEnroll(name, token, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.start_enrollment(name));
+                    reply.send(result).ok();
+                }
+                CustomEvent::PlayerReady(name, token, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.set_ready(name));
+                    if result.is_ok() {
+                        println!("Player ready: {}", name);
+                    }
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::PlayerUndo(name, token, moves, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.undo(name, *moves));
+                    if let Ok(moves) = &result {
+                        println!("Player undo: {} {} moves", name, moves);
+                    }
+                    reply.send(result.map(|moves| moves.to_string())).ok();
+                }
+                CustomEvent::PlayerSetTeam(name, token, team, reply) => {
+                    let result = self
+                        .authorize(name, token)
+                        .and_then(|_| self.set_team(name, team.clone()));
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::PlayerSetMode(name, token, mode, reply) => {
+                    let result = self.authorize(name, token);
+                    if result.is_ok() {
+                        self.get_player(name).control_mode = *mode;
+                        println!("Player control mode: {} {:?}", name, mode);
+                    }
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::Regenerate(size, reply) => {
+                    match size {
+                        Some(size) => self.regenerate(*size),
+                        None => self.restart(),
+                    }
+                    println!("Maze regenerated, size {}", self.maze.size());
+                    reply.send(Ok(String::new())).ok();
+                }
+                CustomEvent::KickPlayer(name, reply) => {
+                    reply.send(self.kick(name).map(|_| String::new())).ok();
+                }
+                CustomEvent::RenamePlayer(name, new_name, reply) => {
+                    let result = self.rename(name, new_name);
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::ResetPlayer(name, reply) => {
+                    reply
+                        .send(self.reset_player(name).map(|_| String::new()))
+                        .ok();
+                }
+                CustomEvent::SetPaused(paused, reply) => {
+                    self.set_paused(*paused);
+                    reply.send(Ok(String::new())).ok();
+                }
+                CustomEvent::StartCountdown(reply) => {
+                    let result = match self.phase {
+                        Phase::Lobby => {
+                            self.start_countdown();
+                            Ok(String::new())
+                        }
+                        phase => Err(CommandError::NotPlaying(phase)),
+                    };
+                    reply.send(result).ok();
+                }
+                CustomEvent::AddBot(name, level, reply) => {
+                    let result = self.add_bot(Bot::new(name, *level));
+                    reply.send(result.map(|_| String::new())).ok();
+                }
+                CustomEvent::StateRequested(sender) => {
+                    sender.send(self.state()).ok();
+                }
+                CustomEvent::MazeRequested(sender) => {
+                    sender.send(self.maze_state()).ok();
+                }
+                CustomEvent::StatsRequested(sender) => {
+                    sender.send(self.leaderboard()).ok();
+                }
+                CustomEvent::Subscribe(sender) => {
+                    let snapshot = GameEvent::Snapshot {
+                        state: Box::new(self.state()),
+                        maze: self.maze_state(),
+                    };
+                    if sender.send(snapshot).is_ok() {
+                        self.subscribers.push(sender.clone());
+                    }
+                }
+            }
         }
     }
 
+    // Local players steer with their keys like with single move commands, shift runs.
+    // In the lobby any of their keys readies them.
     pub fn handle_input(&mut self, _window: &AppWindow, input: &WinitInputHelper) {
         self.input = input.clone();
+        let movement = if input.held_shift() {
+            Movement::Run
+        } else {
+            Movement::Steps(1)
+        };
+        for local_player in self.local_players.clone() {
+            if !self.players.iter().any(|p| p.name == local_player.name) {
+                continue;
+            }
+            for (key, dir) in local_player.bindings.keys().iter() {
+                if input.key_pressed(*key) && self.phase == Phase::Lobby {
+                    if self.set_ready(&local_player.name).is_ok() {
+                        println!("Player ready: {}", local_player.name);
+                    }
+                } else if input.key_pressed(*key) {
+                    // Walls & cooldowns just ignore the key press
+                    if self
+                        .steer(&local_player.name, Steering::Absolute(*dir), movement)
+                        .is_ok()
+                    {
+                        println!(
+                            "Player move: {} {:?} {:?}",
+                            local_player.name, dir, movement
+                        );
+                    }
+                }
+            }
+        }
     }
 
-    fn try_move(&mut self, player: &str, dir: Direction) {
-        let grid_dir = dir.grid_dir();
-        let player_pos = self.get_player(player).pos;
-        let target_cell = self
-            .maze
-            .cell_at(player_pos.x + grid_dir.0, player_pos.y + grid_dir.1)
-            .cloned();
-        let curr_cell = self
-            .maze
-            .cell_at(player_pos.x, player_pos.y)
-            .unwrap()
-            .clone();
-        if let Some(new_cell) = target_cell {
-            if curr_cell.has_link_to(&new_cell) {
-                self.get_player(player).move_to(&new_cell);
-                if new_cell.pos().x == self.maze.end_pos().x
-                    && new_cell.pos().y == self.maze.end_pos().y
-                {
-                    self.is_finished = true;
-                    self.winner = Some(player.to_string());
+    // Resolves steering against player's control mode & heading, turns answer 0 cells moved
+    fn steer(
+        &mut self,
+        player: &str,
+        steering: Steering,
+        movement: Movement,
+    ) -> Result<u32, CommandError> {
+        self.check_playing()?;
+        if self.mode == GameMode::Coop && player != TEAM_NAME {
+            return self.vote(player, steering, movement);
+        }
+        if self.get_player(player).finish_time.is_some() {
+            return Err(CommandError::AlreadyFinished);
+        }
+        let heading = self.get_player(player).heading;
+        match self.resolve_steering(player, steering) {
+            Steering::Absolute(dir) => self.try_move(player, dir, movement),
+            Steering::Forward => self.try_move(player, heading, movement),
+            Steering::Back => {
+                let steps = self.try_move(player, heading.opposite(), movement)?;
+                // Backing up keeps facing the same way
+                let p = self.get_player(player);
+                p.heading = p.heading.opposite();
+                Ok(steps)
+            }
+            Steering::TurnLeft => {
+                self.turn(player, heading.prev());
+                Ok(0)
+            }
+            Steering::TurnRight => {
+                self.turn(player, heading.next());
+                Ok(0)
+            }
+        }
+    }
+
+    // Connected players, by name, who steer the team in coop mode
+    fn voters(&self) -> Vec<String> {
+        let mut voters = self
+            .players
+            .iter()
+            .filter(|p| p.dropped.is_none() && p.name != TEAM_NAME)
+            .map(|p| p.name.clone())
+            .collect::<Vec<String>>();
+        voters.sort();
+        voters
+    }
+
+    fn current_turn(&self, voters: &[String]) -> Option<String> {
+        self.turn
+            .clone()
+            .filter(|turn| voters.contains(turn))
+            .or_else(|| voters.first().cloned())
+    }
+
+    // Player's command steers the team's avatar by the vote rule. Answers the cells the team moved,
+    // 0 for a vote that is still open.
+    fn vote(
+        &mut self,
+        player: &str,
+        steering: Steering,
+        movement: Movement,
+    ) -> Result<u32, CommandError> {
+        // Player's own control mode decides what their words mean
+        let steering = self.resolve_steering(player, steering);
+        let voters = self.voters();
+        match self.vote_rule {
+            VoteRule::Majority => {
+                let vote_window = self.vote_window;
+                self.votes.add(player, steering, movement, vote_window);
+                self.broadcast(GameEvent::PlayerVoted {
+                    name: player.to_string(),
+                    vote: describe(steering, movement),
+                });
+                if self.votes.len() >= voters.len() {
+                    self.resolve_votes()
+                } else {
+                    Ok(0)
+                }
+            }
+            VoteRule::Turns => {
+                let turn = self.current_turn(&voters).unwrap_or_default();
+                if turn != player {
+                    return Err(CommandError::NotYourTurn(turn));
+                }
+                let steps = self.steer(TEAM_NAME, steering, movement)?;
+                self.turn = next_turn(&voters, player);
+                Ok(steps)
+            }
+            VoteRule::Directions => {
+                let direction = steering.direction(self.get_player(TEAM_NAME).heading);
+                if direction_owner(&voters, direction).map(|owner| owner.as_str()) != Some(player) {
+                    return Err(CommandError::NotYourDirection(direction));
                 }
+                self.steer(TEAM_NAME, steering, movement)
             }
         }
     }
 
-    fn get_player(&mut self, name: &str) -> &mut Player {
-        self.players.iter_mut().find(|p| p.name == name).unwrap()
+    // Moves the team by the most voted move
+    fn resolve_votes(&mut self) -> Result<u32, CommandError> {
+        match self.votes.take_winner() {
+            Some((steering, movement)) => {
+                let steps = self.steer(TEAM_NAME, steering, movement)?;
+                println!("Team move: {}", describe(steering, movement));
+                Ok(steps)
+            }
+            None => Ok(0),
+        }
     }
 
-    pub fn winner_name(&mut self) -> Option<String> {
-        if let Some(winner) = self.winner.clone() {
-            Some(format!("{}", self.get_player(&winner).name))
-        } else {
-            None
+    // In relative control mode up/down/left/right mean forward/back/turn left/turn right
+    fn resolve_steering(&mut self, player: &str, steering: Steering) -> Steering {
+        match (steering, self.get_player(player).control_mode) {
+            (Steering::Absolute(Direction::Up), ControlMode::Relative) => Steering::Forward,
+            (Steering::Absolute(Direction::Down), ControlMode::Relative) => Steering::Back,
+            (Steering::Absolute(Direction::Left), ControlMode::Relative) => Steering::TurnLeft,
+            (Steering::Absolute(Direction::Right), ControlMode::Relative) => Steering::TurnRight,
+            (steering, _) => steering,
         }
     }
 
-    pub fn update(&mut self, window: &mut AppWindow, _dt: f64) {
-        if self.is_finished {
+    // Picks the most confident of recognized candidates that is a legal move from player's cell
+    fn steer_best_guess(
+        &mut self,
+        player: &str,
+        candidates: &[(Steering, f32)],
+    ) -> Result<(u32, Steering), CommandError> {
+        let mut confident = candidates
+            .iter()
+            .filter(|(_, confidence)| *confidence >= self.min_confidence)
+            .cloned()
+            .collect::<Vec<(Steering, f32)>>();
+        if confident.is_empty() {
+            let best = candidates.iter().map(|(_, c)| *c).fold(0., f32::max);
+            return Err(CommandError::LowConfidence(best));
+        }
+        confident.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        // In coop mode it's the team that moves
+        let mover = match self.mode {
+            GameMode::Coop => TEAM_NAME,
+            GameMode::Race | GameMode::Tag | GameMode::Score => player,
+        };
+        let (pos, heading) = {
+            let p = self.get_player(mover);
+            (p.pos, p.heading)
+        };
+        let legal =
+            confident.iter().find(
+                |(steering, _)| match self.resolve_steering(player, *steering) {
+                    Steering::TurnLeft | Steering::TurnRight => true,
+                    steering => self
+                        .maze
+                        .linked_cell(pos, steering.direction(heading))
+                        .is_some(),
+                },
+            );
+        // Nothing fits, let the most confident one bump into its wall
+        let (steering, _) = legal.unwrap_or(&confident[0]);
+        let steps = self.steer(player, *steering, Movement::Steps(1))?;
+        Ok((steps, *steering))
+    }
+
+    // Puts player back where they were before their last moves, returns the number of moves undone.
+    // Each undone move counts against the undo limit and adds the undo penalty to the cooldown.
+    fn undo(&mut self, player: &str, moves: u32) -> Result<u32, CommandError> {
+        self.check_playing()?;
+        if self.get_player(player).finish_time.is_some() {
+            return Err(CommandError::AlreadyFinished);
+        }
+        let (undo_limit, undo_penalty) = (self.undo_limit, self.undo_penalty);
+        let p = self.get_player(player);
+        if p.history.is_empty() {
+            return Err(CommandError::NothingToUndo);
+        }
+        let allowed = match undo_limit {
+            Some(limit) if p.undos >= limit => return Err(CommandError::UndoLimit(limit)),
+            Some(limit) => limit - p.undos,
+            None => u32::MAX,
+        };
+        let moves = moves.min(allowed).min(p.history.len() as u32);
+        let index = p.history.len() - moves as usize;
+        let (pos, heading) = p.history[index];
+        p.history.truncate(index);
+        p.pos = pos;
+        p.heading = heading;
+        p.undos += moves;
+        p.move_cooldown += undo_penalty * moves as f64;
+        self.broadcast(GameEvent::PlayerRewound {
+            name: player.to_string(),
+            moves,
+            x: pos.x,
+            y: pos.y,
+        });
+        Ok(moves)
+    }
+
+    fn turn(&mut self, player: &str, heading: Direction) {
+        let p = self.get_player(player);
+        p.remember();
+        p.heading = heading;
+        self.broadcast(GameEvent::PlayerTurned {
+            name: player.to_string(),
+            heading,
+        });
+    }
+
+    // Moves player as far as movement allows and returns the number of cells moved.
+    // The whole movement counts as one move.
+    fn try_move(
+        &mut self,
+        player: &str,
+        dir: Direction,
+        movement: Movement,
+    ) -> Result<u32, CommandError> {
+        let move_cooldown = self.get_player(player).move_cooldown;
+        if move_cooldown > 0. {
+            return Err(CommandError::TooFast(move_cooldown.ceil() as u64));
+        }
+        // Tag & score attack have no goal
+        let end_pos = match self.mode {
+            GameMode::Tag | GameMode::Score => None,
+            _ => Some(self.maze.end_pos()),
+        };
+        let mut pos = self.get_player(player).pos;
+        let mut heading = dir;
+        let mut steps = 0;
+        let mut passed = vec![];
+        while let Some(next_cell) = self.maze.linked_cell(pos, heading) {
+            pos = next_cell.pos();
+            passed.push(pos);
+            steps += 1;
+            if Some(pos) == end_pos {
+                break;
+            }
+            match movement {
+                Movement::Steps(max_steps) => {
+                    if steps >= max_steps {
+                        break;
+                    }
+                }
+                // Follow the corridor around bends, stop at junctions & dead ends
+                Movement::Run => {
+                    let directions = next_cell.available_directions();
+                    if directions.len() != 2 {
+                        break;
+                    }
+                    let came_from = heading.opposite();
+                    heading = *directions.iter().find(|d| **d != came_from).unwrap();
+                }
+            }
+        }
+        if steps == 0 {
+            return Err(CommandError::Blocked(dir));
+        }
+        let new_cell = self.maze.cell_at(pos.x, pos.y).unwrap().clone();
+        let move_interval = self.move_interval;
+        let moved_player = self.get_player(player);
+        moved_player.remember();
+        moved_player.move_to(&new_cell);
+        moved_player.move_cooldown = move_interval;
+        moved_player.heading = heading;
+        moved_player.moves += 1;
+        self.broadcast(GameEvent::PlayerMoved {
+            name: player.to_string(),
+            direction: dir,
+            steps,
+            x: pos.x,
+            y: pos.y,
+        });
+        self.collect_items(player, &passed);
+        self.monster_catch(pos);
+        if self.mode == GameMode::Tag {
+            self.catch(player, pos);
+        }
+        if Some(pos) == end_pos {
+            let race_time = self.race_time;
+            self.get_player(player).finish_time = Some(race_time);
+            let place = self
+                .players
+                .iter()
+                .filter(|p| p.finish_time.is_some())
+                .count() as u32;
+            if place == 1 {
+                self.winner = Some(player.to_string());
+                self.broadcast(GameEvent::PlayerWon {
+                    name: player.to_string(),
+                });
+            }
+            self.broadcast(GameEvent::PlayerFinished {
+                name: player.to_string(),
+                place,
+                time: race_time as u64,
+            });
+            println!(
+                "Player finished: {} #{} {:.1} s",
+                player,
+                place,
+                race_time / 1000.
+            );
+            self.finish_team(player);
+            self.finish_if_done();
+        }
+        Ok(steps)
+    }
+
+    // Announces player's team reaching the goal, if the player's finish completed it
+    fn finish_team(&mut self, player: &str) {
+        let team = match self.get_player(player).team.clone() {
+            Some(team) => team,
+            None => return,
+        };
+        let ranking = self
+            .team_rankings()
+            .into_iter()
+            .find(|t| t.name == team && t.time.is_some());
+        if let Some(ranking) = ranking {
+            // With the first scoring, only the first member's finish completes the team
+            if self.team_scoring == TeamScoring::All || ranking.finished == 1 {
+                let time = ranking.time.unwrap_or_default();
+                println!(
+                    "Team finished: {} #{} {:.1} s",
+                    team,
+                    ranking.place,
+                    time as f64 / 1000.
+                );
+                self.broadcast(GameEvent::TeamFinished {
+                    team,
+                    place: ranking.place,
+                    time,
+                });
+            }
+        }
+    }
+
+    // Sends event to all subscribers, dropping the ones that have hung up
+    fn broadcast(&mut self, event: GameEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn get_player(&mut self, name: &str) -> &mut Player {
+        self.players.iter_mut().find(|p| p.name == name).unwrap()
+    }
+
+    pub fn update(&mut self, window: &mut AppWindow, dt: f64) {
+        for player in self.players.iter_mut() {
+            player.move_cooldown = (player.move_cooldown - dt).max(0.);
+            if let Some(grace) = player.dropped.as_mut() {
+                *grace -= dt;
+            }
+        }
+        let expired = self
+            .players
+            .iter()
+            .filter(|p| p.dropped.is_some_and(|grace| grace <= 0.))
+            .map(|p| p.name.clone())
+            .collect::<Vec<String>>();
+        for name in expired {
+            self.remove_player(&name);
+            println!("Player did not reconnect: {}", name);
+        }
+        if self.phase == Phase::Lobby {
+            self.ready_bots();
+        }
+        if self.phase == Phase::Countdown && !self.paused {
+            self.countdown -= dt;
+            if self.countdown <= 0. {
+                self.set_phase(Phase::Playing);
+            }
+        }
+        if self.phase == Phase::Playing && !self.paused {
+            self.move_bots(dt);
+            self.move_monsters(dt);
+            if self.votes.tick(dt) {
+                if let Err(e) = self.resolve_votes() {
+                    println!("Team move failed: {}", e);
+                }
+            }
+            if self.mode == GameMode::Tag {
+                self.check_it();
+                let it = self.it.clone();
+                for player in self.players.iter_mut() {
+                    if player.dropped.is_none() && Some(&player.name) != it.as_ref() {
+                        player.free_time += dt;
+                    }
+                }
+            }
+            self.race_time += dt;
+            if self.time_limit.is_some_and(|limit| self.race_time >= limit) {
+                println!("Time is up");
+                self.finish_race();
+            }
+        }
+        if self.phase == Phase::Finished {
             return;
         }
         // Update game logic based on inputs here, then render
         self.render_grid(window);
         self.render_players(window);
+        self.render_monsters(window);
+    }
+
+    // Monsters are dark with red eyes, so they stand out from players
+    fn render_monsters(&self, window: &mut AppWindow) {
+        let size = self.cell_size * 2 / 3;
+        let eye = (size / 5).max(1);
+        for monster in self.monsters.iter() {
+            let start_x = self.camera_pos.x - self.maze.size() * self.wall_padding / 2
+                + monster.pos.x * (self.cell_size + self.wall_padding)
+                + self.cell_size / 2
+                - size / 2;
+            let start_y = self.camera_pos.y - self.maze.size() * self.wall_padding / 2
+                + monster.pos.y * (self.cell_size + self.wall_padding)
+                + self.cell_size / 2
+                - size / 2;
+            self.color_rect(
+                window,
+                start_x,
+                start_y,
+                size,
+                size,
+                Color::new(255, 30, 0, 40),
+            );
+            let red = Color::new(255, 255, 0, 0);
+            self.color_rect(window, start_x + eye, start_y + eye, eye, eye, red);
+            self.color_rect(
+                window,
+                start_x + size - 2 * eye,
+                start_y + eye,
+                eye,
+                eye,
+                red,
+            );
+        }
     }
 
     fn render_players(&mut self, window: &mut AppWindow) {
         // Shuffle so they are sometimes rendered in different order to show players are in same cell
         self.players.shuffle(&mut thread_rng());
         for player in self.players.iter() {
+            // In coop mode only the team's avatar is on the board
+            if self.mode == GameMode::Coop && player.name != TEAM_NAME {
+                continue;
+            }
             let start_x = self.camera_pos.x - self.maze.size() * self.wall_padding / 2
                 + player.pos.x * (self.cell_size + self.wall_padding)
-                + self.cell_size / 2 as i32
+                + self.cell_size / 2
                 - player.size / 2;
             let start_y = self.camera_pos.y - self.maze.size() * self.wall_padding / 2
                 + player.pos.y * (self.cell_size + self.wall_padding)
-                + self.cell_size / 2 as i32
+                + self.cell_size / 2
                 - player.size / 2;
-            self.color_rect(
-                window,
-                start_x,
-                start_y,
-                player.size,
-                player.size,
-                player.color,
-            );
+            // Players whose connection has dropped are drawn greyed out
+            let color = match player.dropped {
+                Some(_) => Color::new(255, 120, 120, 120),
+                None => player.color,
+            };
+            // It is framed in red
+            if self.it.as_ref() == Some(&player.name) {
+                let frame = (player.size / 5).max(2);
+                self.color_rect(
+                    window,
+                    start_x - frame,
+                    start_y - frame,
+                    player.size + 2 * frame,
+                    player.size + 2 * frame,
+                    Color::new(255, 255, 0, 0),
+                );
+            }
+            self.color_rect(window, start_x, start_y, player.size, player.size, color);
+            // Team's heading matters to whoever steers it relatively
+            if player.control_mode == ControlMode::Relative || self.is_team(player) {
+                self.render_heading(window, start_x, start_y, player.size, player.heading);
+            }
+        }
+    }
+
+    // Arrow (triangle) pointing to heading, drawn one row at a time from the tip inwards
+    fn render_heading(
+        &self,
+        window: &mut AppWindow,
+        start_x: i32,
+        start_y: i32,
+        size: i32,
+        heading: Direction,
+    ) {
+        let color = Color::new(255, 0, 0, 0);
+        let center = size / 2;
+        for row in 0..center {
+            let (x, y, width, height) = match heading {
+                Direction::Up => (center - row, row, 2 * row + 1, 1),
+                Direction::Down => (center - row, size - 1 - row, 2 * row + 1, 1),
+                Direction::Left => (row, center - row, 1, 2 * row + 1),
+                Direction::Right => (size - 1 - row, center - row, 1, 2 * row + 1),
+            };
+            self.color_rect(window, start_x + x, start_y + y, width, height, color);
         }
     }
 
@@ -234,6 +1844,9 @@ impl MazeGame {
                         self.cell_size,
                         cell.color(),
                     );
+                    if let Some(item) = cell.item() {
+                        self.render_item(window, start_x, start_y, item);
+                    }
                     // Render doors
                     cell.available_directions().iter().for_each(|dir| {
                         let mut start_x = start_x;
@@ -242,7 +1855,7 @@ impl MazeGame {
                             Direction::Up => {
                                 if let Some(opposite) = self.maze.cell_at(maze_x, maze_y - 1) {
                                     if cell.has_link_to(opposite) {
-                                        start_y = start_y - self.wall_padding;
+                                        start_y -= self.wall_padding;
                                         self.color_rect(
                                             window,
                                             start_x,
@@ -257,7 +1870,7 @@ impl MazeGame {
                             Direction::Right => {
                                 if let Some(opposite) = self.maze.cell_at(maze_x + 1, maze_y) {
                                     if cell.has_link_to(opposite) {
-                                        start_x = start_x + self.cell_size;
+                                        start_x += self.cell_size;
                                         self.color_rect(
                                             window,
                                             start_x,
@@ -272,7 +1885,7 @@ impl MazeGame {
                             Direction::Left => {
                                 if let Some(opposite) = self.maze.cell_at(maze_x - 1, maze_y) {
                                     if cell.has_link_to(opposite) {
-                                        start_x = start_x - self.wall_padding;
+                                        start_x -= self.wall_padding;
                                         self.color_rect(
                                             window,
                                             start_x,
@@ -287,7 +1900,7 @@ impl MazeGame {
                             Direction::Down => {
                                 if let Some(opposite) = self.maze.cell_at(maze_x, maze_y + 1) {
                                     if cell.has_link_to(opposite) {
-                                        start_y = start_y + self.cell_size;
+                                        start_y += self.cell_size;
                                         self.color_rect(
                                             window,
                                             start_x,
@@ -306,6 +1919,28 @@ impl MazeGame {
         }
     }
 
+    // Coins are small gold squares, gems larger blue diamonds drawn one row at a time
+    fn render_item(&self, window: &mut AppWindow, start_x: i32, start_y: i32, item: Item) {
+        let center = self.cell_size / 2;
+        match item {
+            Item::Coin => {
+                let size = (self.cell_size / 4).max(2);
+                let color = Color::new(255, 255, 200, 0);
+                let (x, y) = (start_x + center - size / 2, start_y + center - size / 2);
+                self.color_rect(window, x, y, size, size, color);
+            }
+            Item::Gem => {
+                let radius = (self.cell_size / 4).max(2);
+                let color = Color::new(255, 60, 160, 255);
+                for row in 0..=radius {
+                    let (x, width) = (start_x + center - row, 2 * row + 1);
+                    self.color_rect(window, x, start_y + center - radius + row, width, 1, color);
+                    self.color_rect(window, x, start_y + center + radius - row, width, 1, color);
+                }
+            }
+        }
+    }
+
     fn color_rect(
         &self,
         window: &mut AppWindow,
@@ -331,3 +1966,306 @@ impl MazeGame {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tournament::POINTS;
+    use std::sync::mpsc::channel;
+
+    // Game without a window, stats aren't saved
+    fn game(args: &[&str]) -> MazeGame {
+        let args = args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        let mut config = Config::from_args(&args).unwrap();
+        config.stats = Stats::default();
+        MazeGame::with_window_size(&config, (800, 600))
+    }
+
+    // 4x4 cells, start top left & goal bottom right:
+    // S - . - .   .
+    // |       |
+    // .       .
+    //         |
+    //     . - . - .
+    //         |   |
+    //         .   G
+    fn corridors() -> MazeGrid {
+        MazeGrid::with_passages(
+            4,
+            (0, 0),
+            (3, 3),
+            &[
+                ((0, 0), Direction::Right),
+                ((1, 0), Direction::Right),
+                ((2, 0), Direction::Down),
+                ((2, 1), Direction::Down),
+                ((2, 2), Direction::Left),
+                ((2, 2), Direction::Right),
+                ((2, 2), Direction::Down),
+                ((3, 2), Direction::Down),
+                ((0, 0), Direction::Down),
+            ],
+        )
+    }
+
+    // Racing on the corridors maze, with okko at the start
+    fn race(args: &[&str]) -> MazeGame {
+        let mut args = args.to_vec();
+        args.push("--no-lobby");
+        let mut game = game(&args);
+        game.maze = corridors();
+        game.add_player("okko").unwrap();
+        game
+    }
+
+    fn pos(game: &mut MazeGame, name: &str) -> (i32, i32) {
+        let pos = game.get_player(name).pos;
+        (pos.x, pos.y)
+    }
+
+    fn right() -> Steering {
+        Steering::Absolute(Direction::Right)
+    }
+
+    #[test]
+    fn run_follows_bends_to_a_junction() {
+        let mut game = race(&[]);
+        assert_eq!(game.steer("okko", right(), Movement::Run), Ok(4));
+        assert_eq!(pos(&mut game, "okko"), (2, 2));
+        assert_eq!(game.get_player("okko").heading, Direction::Down);
+        assert_eq!(game.get_player("okko").moves, 1);
+    }
+
+    #[test]
+    fn run_stops_at_a_dead_end() {
+        let mut game = race(&[]);
+        let down = Steering::Absolute(Direction::Down);
+        assert_eq!(game.steer("okko", down, Movement::Run), Ok(1));
+        assert_eq!(pos(&mut game, "okko"), (0, 1));
+        game.steer("okko", right(), Movement::Run).unwrap_err();
+        game.get_player("okko").pos = Vector2D::new(2, 2);
+        let left = Steering::Absolute(Direction::Left);
+        assert_eq!(game.steer("okko", left, Movement::Run), Ok(1));
+        assert_eq!(pos(&mut game, "okko"), (1, 2));
+    }
+
+    #[test]
+    fn run_stops_at_the_goal() {
+        let mut game = race(&[]);
+        game.get_player("okko").pos = Vector2D::new(2, 2);
+        assert_eq!(game.steer("okko", right(), Movement::Run), Ok(2));
+        assert_eq!(pos(&mut game, "okko"), (3, 3));
+        assert!(game.get_player("okko").finish_time.is_some());
+    }
+
+    #[test]
+    fn steps_stop_at_a_wall() {
+        let mut game = race(&[]);
+        assert_eq!(game.steer("okko", right(), Movement::Steps(3)), Ok(2));
+        assert_eq!(pos(&mut game, "okko"), (2, 0));
+        assert_eq!(
+            game.steer("okko", right(), Movement::Steps(3)),
+            Err(CommandError::Blocked(Direction::Right))
+        );
+        assert_eq!(game.get_player("okko").moves, 1);
+    }
+
+    #[test]
+    fn move_answers_cells_moved() {
+        let mut game = race(&[]);
+        let token = game.get_player("okko").id.to_string();
+        let (reply, receiver) = channel();
+        let event = CustomEvent::PlayerMove(
+            "okko".to_string(),
+            token,
+            right(),
+            Movement::Steps(5),
+            reply,
+        );
+        game.handle_custom_events(&Event::UserEvent(event));
+        assert_eq!(receiver.recv(), Ok(Ok("2".to_string())));
+    }
+
+    #[test]
+    fn undo_goes_back_as_far_as_history() {
+        let mut game = race(&[]);
+        game.steer("okko", right(), Movement::Steps(1)).unwrap();
+        game.steer("okko", right(), Movement::Steps(1)).unwrap();
+        assert_eq!(game.undo("okko", 5), Ok(2));
+        assert_eq!(pos(&mut game, "okko"), (0, 0));
+        assert_eq!(game.get_player("okko").heading, Direction::Right);
+        assert_eq!(game.undo("okko", 1), Err(CommandError::NothingToUndo));
+    }
+
+    #[test]
+    fn undo_limit_counts_moves() {
+        let mut game = race(&["--undo-limit", "1"]);
+        game.steer("okko", right(), Movement::Steps(1)).unwrap();
+        game.steer("okko", right(), Movement::Steps(1)).unwrap();
+        assert_eq!(game.undo("okko", 2), Ok(1));
+        assert_eq!(pos(&mut game, "okko"), (1, 0));
+        assert_eq!(game.undo("okko", 1), Err(CommandError::UndoLimit(1)));
+    }
+
+    #[test]
+    fn undo_penalty_delays_next_move() {
+        let mut game = race(&["--undo-penalty", "500"]);
+        game.steer("okko", right(), Movement::Steps(1)).unwrap();
+        assert_eq!(game.undo("okko", 1), Ok(1));
+        assert_eq!(
+            game.steer("okko", right(), Movement::Steps(1)),
+            Err(CommandError::TooFast(500))
+        );
+    }
+
+    // Players on teams, with finish times in ms for those who reached the goal
+    fn teams(args: &[&str], players: &[(&str, &str, Option<f64>)]) -> MazeGame {
+        let mut game = game(args);
+        for (name, team, finish_time) in players.iter() {
+            game.add_player(name).unwrap();
+            game.set_team(name, Some(team.to_string())).unwrap();
+            game.get_player(name).finish_time = *finish_time;
+        }
+        game
+    }
+
+    fn team_times(game: &MazeGame) -> Vec<(String, Option<u64>)> {
+        game.team_rankings()
+            .into_iter()
+            .map(|t| (t.name, t.time))
+            .collect()
+    }
+
+    #[test]
+    fn team_finishes_with_its_last_member() {
+        let game = teams(
+            &[],
+            &[
+                ("okko", "red", Some(1000.)),
+                ("sini", "red", Some(9000.)),
+                ("aapo", "blue", Some(5000.)),
+                ("elli", "blue", Some(6000.)),
+                ("nea", "green", Some(500.)),
+                ("ukko", "green", None),
+            ],
+        );
+        assert_eq!(
+            team_times(&game),
+            vec![
+                ("blue".to_string(), Some(6000)),
+                ("red".to_string(), Some(9000)),
+                ("green".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn team_finishes_with_its_first_member() {
+        let game = teams(
+            &["--team-scoring", "first"],
+            &[
+                ("okko", "red", Some(1000.)),
+                ("sini", "red", Some(9000.)),
+                ("aapo", "blue", Some(5000.)),
+                ("elli", "blue", None),
+                ("nea", "green", None),
+            ],
+        );
+        assert_eq!(
+            team_times(&game),
+            vec![
+                ("red".to_string(), Some(1000)),
+                ("blue".to_string(), Some(5000)),
+                ("green".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unfinished_teams_rank_by_members_finished() {
+        let game = teams(
+            &[],
+            &[
+                ("okko", "red", None),
+                ("sini", "blue", Some(1000.)),
+                ("aapo", "blue", None),
+                ("elli", "green", None),
+            ],
+        );
+        let rankings = game.team_rankings();
+        let order = rankings
+            .iter()
+            .map(|t| (t.place, t.name.as_str(), t.finished))
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![(1, "blue", 1), (2, "green", 0), (3, "red", 0)]);
+    }
+
+    // Monster waiting where okko is
+    fn monster_at_player(game: &mut MazeGame) -> Vector2D<i32, i32> {
+        let pos = game.get_player("okko").pos;
+        game.monsters = Monster::spawn(&game.maze, 1);
+        game.monsters[0].pos = pos;
+        pos
+    }
+
+    #[test]
+    fn caught_player_goes_back_to_start() {
+        let mut game = race(&["--monster-penalty", "start"]);
+        game.steer("okko", right(), Movement::Run).unwrap();
+        let pos = monster_at_player(&mut game);
+        game.monster_catch(pos);
+        let okko = game.get_player("okko");
+        assert_eq!((okko.pos.x, okko.pos.y), (0, 0));
+        assert_eq!(okko.heading, Direction::Right);
+        assert!(okko.history.is_empty());
+    }
+
+    #[test]
+    fn caught_player_loses_points() {
+        let mut game = race(&["--monster-penalty", "points"]);
+        game.steer("okko", right(), Movement::Run).unwrap();
+        let cell = monster_at_player(&mut game);
+        game.get_player("okko").points = MONSTER_POINTS + 2;
+        game.monster_catch(cell);
+        assert_eq!(game.get_player("okko").points, 2);
+        game.monster_catch(cell);
+        assert_eq!(game.get_player("okko").points, 0);
+        assert_eq!(pos(&mut game, "okko"), (2, 2));
+    }
+
+    #[test]
+    fn ties_rank_by_name() {
+        let mut game = race(&[]);
+        for name in ["sini", "aapo", "elli", "nea"].iter() {
+            game.add_player(name).unwrap();
+        }
+        game.get_player("sini").finish_time = Some(4000.);
+        game.get_player("aapo").finish_time = Some(4000.);
+        game.get_player("okko").pos = Vector2D::new(2, 2);
+        game.get_player("nea").pos = Vector2D::new(2, 2);
+        let names = game
+            .rankings()
+            .into_iter()
+            .map(|r| r.name)
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["aapo", "sini", "nea", "okko", "elli"]);
+    }
+
+    #[test]
+    fn bots_dont_outscore_people() {
+        let mut game = game(&["--no-lobby", "--rounds", "2", "--bot", "robo=shortest"]);
+        game.add_player("okko").unwrap();
+        game.get_player("robo").finish_time = Some(1000.);
+        game.get_player("okko").finish_time = Some(2000.);
+        game.finish_race();
+        let standings = game.tournament().unwrap().standings;
+        assert_eq!(standings.len(), 1);
+        assert_eq!(standings[0].name, "okko");
+        assert_eq!(standings[0].points, POINTS[0]);
+        assert_eq!(standings[0].wins, 1);
+        let leaderboard = game.stats.leaderboard();
+        assert_eq!(leaderboard.len(), 1);
+        assert_eq!(leaderboard[0].name, "okko");
+        assert_eq!(leaderboard[0].wins, 1);
+    }
+}
@@ -1,6 +1,15 @@
-use crate::custom_events::CustomEvent;
+use crate::config::{MAX_MAZE_SIZE, MIN_MAZE_SIZE};
+use crate::coop::TEAM_NAME;
+use crate::custom_events::{is_valid_name, CustomEvent};
 use crate::game::MazeGame;
-use imgui::{im_str, Condition, Context, FontSource, MenuItem, MouseCursor, Window as ImguiWindow};
+use crate::state::{
+    CoopState, GameMode, Phase, PlayerState, Ranking, TeamRanking, TournamentState,
+};
+use crate::stats::PlayerStats;
+use imgui::{
+    im_str, Condition, Context, FontSource, ImString, MenuItem, MouseCursor, Slider,
+    Window as ImguiWindow,
+};
 use imgui_wgpu::{Renderer, RendererConfig, RendererResult};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use pixels::raw_window_handle::HasRawWindowHandle;
@@ -9,12 +18,23 @@ use pixels::wgpu::{
     TextureFormat, TextureView,
 };
 use pixels::{Pixels, PixelsContext};
-use raqote::Color;
 use std::time::Instant;
 use winit::error::ExternalError;
 use winit::event::Event;
 use winit::window::Window;
 
+// Admin buttons clicked, applied to the game after the frame
+enum AdminAction {
+    Restart,
+    Regenerate(i32),
+    Kick(String),
+    Rename(String, String),
+    ResetPlayer(String),
+    SetPaused(bool),
+    Ready(String),
+    StartCountdown,
+}
+
 pub struct Gui {
     imgui: Context,
     platform: WinitPlatform,
@@ -26,8 +46,35 @@ pub struct Gui {
     metrics_open: bool,
     end_game_open: bool,
     restart: bool,
-    winner: String,
-    players: Vec<(String, Color)>,
+    rankings: Vec<Ranking>,
+    // Seconds since the race started
+    race_time: f64,
+    players: Vec<PlayerState>,
+    // Moves each player may undo, None if unlimited
+    undo_limit: Option<u32>,
+    // Players enrolling their voice, with the word to say next
+    enrollments: Vec<(String, String)>,
+    // Player whose enroll button was clicked
+    enroll: Option<String>,
+    admin_open: bool,
+    admin_actions: Vec<AdminAction>,
+    paused: bool,
+    // Size for the regenerate button
+    maze_size: i32,
+    new_name: ImString,
+    phase: Phase,
+    // Seconds left of the countdown
+    countdown: u64,
+    leaderboard_open: bool,
+    leaderboard: Vec<PlayerStats>,
+    // Size of the maze being played, the leaderboard shows best times on it
+    leaderboard_size: i32,
+    tournament: Option<TournamentState>,
+    coop: Option<CoopState>,
+    teams: Vec<TeamRanking>,
+    mode: GameMode,
+    // Player chasing the others, in tag mode
+    it: Option<String>,
 }
 
 impl Gui {
@@ -55,8 +102,8 @@ impl Gui {
         let queue = pixels.queue();
         let renderer = Renderer::new(
             &mut imgui,
-            &device,
-            &queue,
+            device,
+            queue,
             RendererConfig::new().set_texture_format(TextureFormat::Bgra8UnormSrgb),
         );
         Gui {
@@ -69,9 +116,28 @@ impl Gui {
             last_cursor: None,
             metrics_open: false,
             end_game_open: false,
-            winner: "".to_string(),
+            rankings: vec![],
+            race_time: 0.,
             restart: false,
             players: vec![],
+            undo_limit: None,
+            enrollments: vec![],
+            enroll: None,
+            admin_open: false,
+            admin_actions: vec![],
+            paused: false,
+            maze_size: 0,
+            new_name: ImString::with_capacity(32),
+            phase: Phase::Lobby,
+            countdown: 0,
+            leaderboard_open: false,
+            leaderboard: vec![],
+            leaderboard_size: 0,
+            tournament: None,
+            coop: None,
+            teams: vec![],
+            mode: GameMode::Race,
+            it: None,
         }
     }
 
@@ -98,17 +164,116 @@ impl Gui {
         }
         let ui = self.imgui.frame();
         let mut metrics_open = false;
+        let mut admin_open = false;
+        let mut leaderboard_open = false;
         ui.main_menu_bar(|| {
             metrics_open = MenuItem::new(im_str!("Metrics")).build(&ui);
+            admin_open = MenuItem::new(im_str!("Admin")).build(&ui);
+            leaderboard_open = MenuItem::new(im_str!("Leaderboard")).build(&ui);
         });
         if metrics_open {
             self.metrics_open = true;
         }
+        if admin_open {
+            self.admin_open = true;
+        }
+        if leaderboard_open {
+            self.leaderboard_open = true;
+        }
+        if self.leaderboard_open {
+            render_leaderboard(
+                &ui,
+                &self.leaderboard,
+                self.leaderboard_size,
+                &mut self.leaderboard_open,
+            );
+        }
+        if self.admin_open {
+            let actions = render_admin(
+                &ui,
+                &self.players,
+                self.paused,
+                &mut self.maze_size,
+                &mut self.new_name,
+                &mut self.admin_open,
+            );
+            self.admin_actions.extend(actions);
+        }
+        if self.paused {
+            ImguiWindow::new(im_str!("Paused"))
+                .title_bar(false)
+                .movable(false)
+                .resizable(false)
+                .position(
+                    [
+                        window.inner_size().width as f32 / window.scale_factor() as f32 / 2. - 50.,
+                        30.,
+                    ],
+                    Condition::Always,
+                )
+                .size([100., 30.], Condition::Always)
+                .build(&ui, || {
+                    ui.text_colored([1., 1., 0., 1.], im_str!("Paused"));
+                });
+        }
         if self.metrics_open {
             ui.show_metrics_window(&mut self.metrics_open);
         }
+        let center = [
+            window.inner_size().width as f32 / window.scale_factor() as f32 / 2.,
+            window.inner_size().height as f32 / window.scale_factor() as f32 / 2.,
+        ];
+        match self.phase {
+            Phase::Lobby => {
+                let players = self.players.clone();
+                let mut actions = vec![];
+                ImguiWindow::new(im_str!("Lobby"))
+                    .movable(false)
+                    .resizable(false)
+                    .collapsible(false)
+                    .position(
+                        [center[0] - 150., center[1] - 150.],
+                        Condition::FirstUseEver,
+                    )
+                    .size([300.0, 300.0], Condition::FirstUseEver)
+                    .build(&ui, || {
+                        ui.text(im_str!("Waiting for everyone to get ready"));
+                        ui.separator();
+                        for p in players.iter().filter(|p| p.connected) {
+                            if p.ready {
+                                ui.text_colored([0., 1., 0., 1.], im_str!("{}: ready", p.name));
+                            } else {
+                                ui.text(im_str!("{}", p.name));
+                                ui.same_line(0.);
+                                if ui.small_button(&im_str!("Ready##{}", p.name)) {
+                                    actions.push(AdminAction::Ready(p.name.clone()));
+                                }
+                            }
+                        }
+                        ui.separator();
+                        if ui.small_button(im_str!("Start now")) {
+                            actions.push(AdminAction::StartCountdown);
+                        }
+                    });
+                self.admin_actions.extend(actions);
+            }
+            Phase::Countdown => {
+                let countdown = self.countdown;
+                ImguiWindow::new(im_str!("Countdown"))
+                    .title_bar(false)
+                    .movable(false)
+                    .resizable(false)
+                    .position([center[0] - 60., center[1] - 20.], Condition::Always)
+                    .size([120., 40.], Condition::Always)
+                    .build(&ui, || {
+                        ui.text_colored([1., 1., 0., 1.], im_str!("Starting in {}", countdown));
+                    });
+            }
+            Phase::Playing | Phase::Finished => (),
+        }
         if self.end_game_open {
-            let winner = self.winner.clone();
+            let rankings = self.rankings.clone();
+            let mode = self.mode;
             ImguiWindow::new(im_str!("Game Over!"))
                 .movable(false)
                 .resizable(false)
@@ -125,32 +290,146 @@ impl Gui {
                 .size([300.0, 300.0], Condition::FirstUseEver)
                 .opened(&mut self.end_game_open)
                 .build(&ui, || {
-                    ui.text_colored([0., 1.0, 0., 1.0], im_str!("Winner: {}", winner));
+                    ui.columns(4, im_str!("Rankings"), false);
+                    for header in [
+                        im_str!("#"),
+                        im_str!("Player"),
+                        match mode {
+                            GameMode::Tag => im_str!("Not it"),
+                            GameMode::Score => im_str!("Points"),
+                            _ => im_str!("Time"),
+                        },
+                        im_str!("Moves"),
+                    ] {
+                        ui.text_disabled(header);
+                        ui.next_column();
+                    }
+                    for ranking in rankings.iter() {
+                        let color = if ranking.place == 1 && ranking.is_scored() {
+                            [0., 1.0, 0., 1.0]
+                        } else {
+                            [1.0, 1.0, 1.0, 1.0]
+                        };
+                        ui.text_colored(color, im_str!("{}", ranking.place));
+                        ui.next_column();
+                        ui.text_colored(color, im_str!("{}", ranking.name));
+                        ui.next_column();
+                        match (ranking.time, ranking.score) {
+                            (None, Some(points)) if mode == GameMode::Score => {
+                                ui.text(im_str!("{}", points))
+                            }
+                            (Some(time), _) | (None, Some(time)) => {
+                                ui.text(im_str!("{:.1} s", time as f64 / 1000.))
+                            }
+                            (None, None) => ui.text_disabled(im_str!("-")),
+                        }
+                        ui.next_column();
+                        ui.text(im_str!("{}", ranking.moves));
+                        ui.next_column();
+                    }
+                    ui.columns(1, im_str!("Rankings"), false);
                 });
             if !self.end_game_open {
                 self.restart = true;
             }
         }
-        let player_names = self.players.clone();
+        if let Some(tournament) = &self.tournament {
+            render_scoreboard(&ui, tournament);
+        }
+        if !self.teams.is_empty() {
+            render_teams(&ui, &self.teams);
+        }
+        let players = self.players.clone();
+        let race_time = self.race_time;
+        let phase = self.phase;
+        let undo_limit = self.undo_limit;
+        let enrollments = self.enrollments.clone();
+        let coop = self.coop.clone();
+        let mode = self.mode;
+        let it = self.it.clone();
+        let mut enroll = None;
         ImguiWindow::new(im_str!("Players!"))
             .movable(false)
             .resizable(false)
             .collapsible(false)
             .position([0., 20.], Condition::FirstUseEver)
-            .size([150.0, 300.0], Condition::FirstUseEver)
+            .size([200.0, 300.0], Condition::FirstUseEver)
             .build(&ui, || {
-                for p in player_names {
-                    ui.text_colored(
-                        [
-                            p.1.r() as f32 / 255.0,
-                            p.1.g() as f32 / 255.0,
-                            p.1.b() as f32 / 255.0,
-                            1.0,
-                        ],
-                        im_str!("{}", p.0),
-                    );
+                if phase == Phase::Playing || phase == Phase::Finished {
+                    ui.text(im_str!("Time: {:.1} s", race_time));
+                    ui.separator();
+                }
+                if let Some(coop) = &coop {
+                    let rule = format!("{:?}", coop.rule).to_lowercase();
+                    ui.text(im_str!("Team steers by {}", rule));
+                    ui.separator();
+                }
+                for p in players {
+                    if p.connected {
+                        ui.text_colored(
+                            [
+                                p.color[0] as f32 / 255.0,
+                                p.color[1] as f32 / 255.0,
+                                p.color[2] as f32 / 255.0,
+                                1.0,
+                            ],
+                            im_str!("{}", p.name),
+                        );
+                    } else {
+                        ui.text_disabled(format!("{} (disconnected)", p.name));
+                    }
+                    ui.same_line(0.);
+                    // Bots have no voice to enroll
+                    match p.bot {
+                        Some(level) => {
+                            ui.text_disabled(format!("{:?} bot", level).to_lowercase());
+                        }
+                        None => {
+                            if ui.small_button(&im_str!("Enroll##{}", p.name)) {
+                                enroll = Some(p.name.clone());
+                            }
+                        }
+                    }
+                    match undo_limit {
+                        Some(limit) => ui.text(im_str!("  undos: {}/{}", p.undos, limit)),
+                        None if p.undos > 0 => ui.text(im_str!("  undos: {}", p.undos)),
+                        None => (),
+                    }
+                    if mode == GameMode::Tag {
+                        if it.as_ref() == Some(&p.name) {
+                            ui.text_colored([1., 0., 0., 1.], im_str!("  it"));
+                        }
+                        ui.text(im_str!("  not it: {:.1} s", p.free_time as f64 / 1000.));
+                    }
+                    if p.items > 0 || mode == GameMode::Score {
+                        ui.text(im_str!("  items: {} ({} points)", p.items, p.points));
+                    }
+                    if let Some((_, prompt)) = enrollments.iter().find(|(name, _)| *name == p.name)
+                    {
+                        ui.text(im_str!("  say: {}", prompt));
+                    }
+                    if let Some(coop) = &coop {
+                        if coop.turn.as_ref() == Some(&p.name) {
+                            ui.text_colored([1., 1., 0., 1.], im_str!("  moves next"));
+                        }
+                        if let Some(vote) = coop.votes.iter().find(|v| v.name == p.name) {
+                            ui.text(im_str!("  votes: {}", vote.vote));
+                        }
+                        let owned = coop
+                            .owners
+                            .iter()
+                            .filter(|o| o.name == p.name)
+                            .map(|o| format!("{:?}", o.direction).to_lowercase())
+                            .collect::<Vec<String>>();
+                        if !owned.is_empty() {
+                            ui.text(im_str!("  owns: {}", owned.join(", ")));
+                        }
+                    }
                 }
             });
+        if enroll.is_some() {
+            self.enroll = enroll;
+        }
         let mouse_cursor = ui.mouse_cursor();
         if self.last_cursor != mouse_cursor {
             self.last_cursor = mouse_cursor;
@@ -172,6 +451,11 @@ impl Gui {
             .render(ui.render(), &context.queue, &context.device, &mut rpass)
     }
 
+    // True while a text field of the gui has focus, so typing there doesn't steer anyone
+    pub fn wants_keyboard(&self) -> bool {
+        self.imgui.io().want_capture_keyboard
+    }
+
     pub fn handle_event(
         &mut self,
         window: &Window,
@@ -184,14 +468,259 @@ impl Gui {
             game.restart();
             self.restart = false;
         }
-        if let Some(winner) = game.winner_name() {
+        if game.phase() == Phase::Finished {
+            if !self.end_game_open {
+                self.rankings = game.rankings();
+            }
             self.end_game_open = true;
-            self.winner = winner;
+        } else {
+            // Game may have been restarted from elsewhere (e.g. http api)
+            self.end_game_open = false;
+        }
+        // Player may have left since the button was drawn
+        if let Some(name) = self.enroll.take() {
+            if let Err(e) = game.start_enrollment(&name) {
+                println!("Enrollment not started: {}", e);
+            }
+        }
+        for action in self.admin_actions.drain(..) {
+            let result = match action {
+                AdminAction::Restart => {
+                    game.restart();
+                    Ok(())
+                }
+                AdminAction::Regenerate(size) => {
+                    game.regenerate(size);
+                    Ok(())
+                }
+                AdminAction::Kick(name) => game.kick(&name),
+                AdminAction::Rename(name, new_name) => game.rename(&name, &new_name),
+                AdminAction::ResetPlayer(name) => game.reset_player(&name),
+                AdminAction::SetPaused(paused) => {
+                    game.set_paused(paused);
+                    Ok(())
+                }
+                AdminAction::Ready(name) => game.set_ready(&name),
+                AdminAction::StartCountdown => {
+                    game.start_countdown();
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                println!("Admin action failed: {}", e);
+            }
+        }
+        if self.maze_size == 0 {
+            self.maze_size = game.maze_size();
+        }
+        self.paused = game.is_paused();
+        self.phase = game.phase();
+        self.race_time = game.race_time() / 1000.;
+        self.countdown = game.countdown_seconds();
+        let state = game.state();
+        self.players = state.players;
+        self.players.sort_by(|a, b| a.name.cmp(&b.name));
+        self.undo_limit = state.undo_limit;
+        self.tournament = state.tournament;
+        // The team's avatar isn't anyone to ready up, enroll or list
+        if state.coop.is_some() {
+            self.players.retain(|p| p.name != TEAM_NAME);
+        }
+        self.coop = state.coop;
+        self.teams = state.teams;
+        self.mode = state.mode;
+        self.it = state.it;
+        self.enrollments = game.enrollments();
+        if self.leaderboard_open {
+            self.leaderboard = game.leaderboard();
+            self.leaderboard_size = game.maze_size();
         }
-        self.players = game.players();
     }
 }
 
+// Returns the buttons clicked
+fn render_admin(
+    ui: &imgui::Ui,
+    players: &[PlayerState],
+    paused: bool,
+    maze_size: &mut i32,
+    new_name: &mut ImString,
+    opened: &mut bool,
+) -> Vec<AdminAction> {
+    let mut actions = vec![];
+    ImguiWindow::new(im_str!("Admin"))
+        .position([1000., 20.], Condition::FirstUseEver)
+        .size([260.0, 300.0], Condition::FirstUseEver)
+        .opened(opened)
+        .build(ui, || {
+            if ui.small_button(im_str!("Restart")) {
+                actions.push(AdminAction::Restart);
+            }
+            ui.same_line(0.);
+            let pause_label = if paused {
+                im_str!("Resume")
+            } else {
+                im_str!("Pause")
+            };
+            if ui.small_button(pause_label) {
+                actions.push(AdminAction::SetPaused(!paused));
+            }
+            Slider::new(im_str!("Size"))
+                .range(MIN_MAZE_SIZE..=MAX_MAZE_SIZE)
+                .build(ui, maze_size);
+            if ui.small_button(im_str!("Regenerate")) {
+                actions.push(AdminAction::Regenerate(*maze_size));
+            }
+            ui.separator();
+            ui.input_text(im_str!("New name"), new_name).build();
+            for p in players {
+                ui.text(im_str!("{}", p.name));
+                ui.same_line(0.);
+                if ui.small_button(&im_str!("Start##{}", p.name)) {
+                    actions.push(AdminAction::ResetPlayer(p.name.clone()));
+                }
+                ui.same_line(0.);
+                if ui.small_button(&im_str!("Kick##{}", p.name)) {
+                    actions.push(AdminAction::Kick(p.name.clone()));
+                }
+                ui.same_line(0.);
+                if ui.small_button(&im_str!("Rename##{}", p.name)) {
+                    let name = new_name.to_str().trim().to_string();
+                    if is_valid_name(&name) {
+                        actions.push(AdminAction::Rename(p.name.clone(), name));
+                        new_name.clear();
+                    }
+                }
+            }
+        });
+    actions
+}
+
+// Points of the tournament so far, final standings after the last round
+fn render_scoreboard(ui: &imgui::Ui, tournament: &TournamentState) {
+    let title = if tournament.over {
+        ImString::new("Final standings###Tournament")
+    } else {
+        im_str!(
+            "Round {}/{}###Tournament",
+            tournament.round,
+            tournament.rounds
+        )
+    };
+    ImguiWindow::new(&title)
+        .position([1000., 340.], Condition::FirstUseEver)
+        .size([260.0, 200.0], Condition::FirstUseEver)
+        .build(ui, || {
+            ui.columns(4, im_str!("Standings"), false);
+            for header in [
+                im_str!("#"),
+                im_str!("Player"),
+                im_str!("Points"),
+                im_str!("Wins"),
+            ] {
+                ui.text_disabled(header);
+                ui.next_column();
+            }
+            for (i, standing) in tournament.standings.iter().enumerate() {
+                let color = if i == 0 && tournament.over {
+                    [0., 1.0, 0., 1.0]
+                } else {
+                    [1.0, 1.0, 1.0, 1.0]
+                };
+                ui.text_colored(color, im_str!("{}", i + 1));
+                ui.next_column();
+                ui.text_colored(color, im_str!("{}", standing.name));
+                ui.next_column();
+                ui.text(im_str!("{}", standing.points));
+                ui.next_column();
+                ui.text(im_str!("{}", standing.wins));
+                ui.next_column();
+            }
+            ui.columns(1, im_str!("Standings"), false);
+        });
+}
+
+// Team standings of the race, in team colors
+fn render_teams(ui: &imgui::Ui, teams: &[TeamRanking]) {
+    ImguiWindow::new(im_str!("Teams"))
+        .position([1000., 560.], Condition::FirstUseEver)
+        .size([260.0, 160.0], Condition::FirstUseEver)
+        .build(ui, || {
+            ui.columns(4, im_str!("Teams"), false);
+            for header in [
+                im_str!("#"),
+                im_str!("Team"),
+                im_str!("Finished"),
+                im_str!("Time"),
+            ] {
+                ui.text_disabled(header);
+                ui.next_column();
+            }
+            for team in teams.iter() {
+                let [r, g, b] = team.color;
+                let color = [r as f32 / 255., g as f32 / 255., b as f32 / 255., 1.0];
+                ui.text(im_str!("{}", team.place));
+                ui.next_column();
+                ui.text_colored(color, im_str!("{}", team.name));
+                ui.next_column();
+                ui.text(im_str!("{}/{}", team.finished, team.members.len()));
+                ui.next_column();
+                match team.time {
+                    Some(time) => ui.text(im_str!("{:.1} s", time as f64 / 1000.)),
+                    None => ui.text_disabled(im_str!("-")),
+                }
+                ui.next_column();
+            }
+            ui.columns(1, im_str!("Teams"), false);
+        });
+}
+
+// Wins, streaks & moves of all sessions, with best times on mazes of the given size
+fn render_leaderboard(
+    ui: &imgui::Ui,
+    leaderboard: &[PlayerStats],
+    maze_size: i32,
+    opened: &mut bool,
+) {
+    ImguiWindow::new(im_str!("Leaderboard"))
+        .position([300., 20.], Condition::FirstUseEver)
+        .size([400.0, 300.0], Condition::FirstUseEver)
+        .opened(opened)
+        .build(ui, || {
+            ui.columns(6, im_str!("Leaderboard"), false);
+            let best = im_str!("Best {}x{}", maze_size, maze_size);
+            for header in [
+                im_str!("Player"),
+                im_str!("Wins"),
+                im_str!("Races"),
+                im_str!("Streak"),
+                im_str!("Moves"),
+                &best,
+            ] {
+                ui.text_disabled(header);
+                ui.next_column();
+            }
+            for player in leaderboard.iter() {
+                ui.text(im_str!("{}", player.name));
+                ui.next_column();
+                ui.text(im_str!("{}", player.wins));
+                ui.next_column();
+                ui.text(im_str!("{}", player.races));
+                ui.next_column();
+                ui.text(im_str!("{} (best {})", player.streak, player.best_streak));
+                ui.next_column();
+                ui.text(im_str!("{}", player.moves));
+                ui.next_column();
+                match player.best_time(maze_size) {
+                    Some(time) => ui.text(im_str!("{:.1} s", time as f64 / 1000.)),
+                    None => ui.text_disabled(im_str!("-")),
+                }
+                ui.next_column();
+            }
+            ui.columns(1, im_str!("Leaderboard"), false);
+        });
+}
+
 fn gamma_to_linear(color: [f32; 4]) -> [f32; 4] {
     const GAMMA: f32 = 2.2;
     let x = color[0].powf(GAMMA);
@@ -0,0 +1,541 @@
+use crate::bots::BotLevel;
+use crate::config::{check_maze_size, Config};
+use crate::custom_events::{
+    is_valid_name, wait_reply, CommandError, ControlMode, CustomEvent, Movement, Reply,
+    MAX_AUDIO_BYTES, REPLY_TIMEOUT,
+};
+use crate::direction::Steering;
+use crate::keyword_spotting::hear;
+use crate::mfcc::Recording;
+use crate::vocabulary::Vocabulary;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+use winit::event_loop::EventLoopProxy;
+
+// Largest json body a request may have, recordings excepted
+const MAX_JSON_BYTES: usize = 4096;
+
+#[derive(Deserialize)]
+struct NewPlayer {
+    name: String,
+    password: Option<String>,
+    team: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Session {
+    token: String,
+}
+
+#[derive(Serialize)]
+struct Reconnected {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Move {
+    // up/right/down/left or forward/back/turn left/turn right
+    direction: Option<String>,
+    // Up to this many cells, defaults to one
+    steps: Option<u32>,
+    // Until the next junction or dead end
+    #[serde(default)]
+    run: bool,
+    // Speech recognizer's confidence for direction
+    confidence: Option<f32>,
+    // Or a ranked list of recognized directions
+    candidates: Option<Vec<Candidate>>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    direction: String,
+    confidence: f32,
+}
+
+impl Move {
+    fn movement(&self) -> Movement {
+        if self.run {
+            Movement::Run
+        } else {
+            Movement::Steps(self.steps.unwrap_or(1).max(1))
+        }
+    }
+
+    fn into_event(
+        self,
+        name: &str,
+        token: String,
+        vocabulary: &Vocabulary,
+        reply: Reply,
+    ) -> Result<CustomEvent, String> {
+        let steering = |direction: &str| vocabulary.translate(direction).parse::<Steering>();
+        let mut candidates = vec![];
+        if let (Some(direction), Some(confidence)) = (&self.direction, self.confidence) {
+            candidates.push((steering(direction)?, confidence));
+        }
+        for candidate in self.candidates.iter().flatten() {
+            candidates.push((steering(&candidate.direction)?, candidate.confidence));
+        }
+        if !candidates.is_empty() {
+            return Ok(CustomEvent::PlayerMoveGuess(
+                name.to_string(),
+                token,
+                candidates,
+                reply,
+            ));
+        }
+        let direction = self.direction.as_ref().ok_or("Missing direction")?;
+        Ok(CustomEvent::PlayerMove(
+            name.to_string(),
+            token,
+            steering(direction)?,
+            self.movement(),
+            reply,
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct Undo {
+    moves: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct NewTeam {
+    // None leaves the team
+    team: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewMode {
+    mode: ControlMode,
+}
+
+#[derive(Deserialize)]
+struct Regenerate {
+    size: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct NewName {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct NewBot {
+    name: String,
+    level: Option<BotLevel>,
+}
+
+// Requests like:
+// curl localhost:8081/state
+// curl localhost:8081/stats (leaderboard of all sessions, or /stats/okko for one player)
+// curl -X POST -d '{"name": "okko"}' localhost:8081/players (answers {"token": "<token>"}, "team": "red" joins a team)
+// curl -X POST -H "Authorization: Bearer <token>" localhost:8081/players/reconnect (answers {"name": "okko"})
+// curl -X POST -H "Authorization: Bearer <token>" -d '{"direction": "left"}' localhost:8081/players/okko/move
+// (also "steps": 3 or "run": true, answers with the number of cells moved)
+// (or "confidence": 0.8, or "candidates": [{"direction": "left", "confidence": 0.7}, ...])
+// curl -X POST -H "Authorization: Bearer <token>" --data-binary @left.wav localhost:8081/players/okko/audio
+// (wav or raw 16 kHz 16 bit pcm, needs --templates or enrolling)
+// curl -X POST -H "Authorization: Bearer <token>" localhost:8081/players/okko/enroll (answers "up 1/3",
+// following audio requests are stored as the player's templates, answering with the next word or "done")
+// curl -X POST -H "Authorization: Bearer <token>" localhost:8081/players/okko/ready
+// curl -X POST -H "Authorization: Bearer <token>" localhost:8081/players/okko/undo
+// (or -d '{"moves": 3}', answers with the number of moves undone)
+// curl -X PUT -H "Authorization: Bearer <token>" -d '{"mode": "relative"}' localhost:8081/players/okko/mode
+// curl -X PUT -H "Authorization: Bearer <token>" -d '{"team": "red"}' localhost:8081/players/okko/team
+// ({"team": null} leaves the team)
+// curl -X DELETE -H "Authorization: Bearer <token>" localhost:8081/players/okko
+// Admin requests need the admin token instead:
+// curl -X POST -H "Authorization: Bearer <admin token>" localhost:8081/restart
+// curl -X POST -H "Authorization: Bearer <admin token>" -d '{"size": 20}' localhost:8081/regenerate
+// curl -X POST -H "Authorization: Bearer <admin token>" localhost:8081/players/okko/kick
+// curl -X POST -H "Authorization: Bearer <admin token>" localhost:8081/players/okko/reset (back to start)
+// curl -X PUT -H "Authorization: Bearer <admin token>" -d '{"name": "ville"}' localhost:8081/players/okko/name
+// curl -X POST -H "Authorization: Bearer <admin token>" localhost:8081/pause (and /resume)
+// curl -X POST -H "Authorization: Bearer <admin token>" localhost:8081/start (countdown, ready or not)
+// curl -X POST -H "Authorization: Bearer <admin token>" -d '{"name": "robo", "level": "wall"}' localhost:8081/bots
+// Each request is handled on its own thread, so one waiting for the game doesn't hold up others
+pub fn serve(server: Server, event_loop_proxy: EventLoopProxy<CustomEvent>, config: &Arc<Config>) {
+    for request in server.incoming_requests() {
+        let event_loop_proxy = event_loop_proxy.clone();
+        let config = config.clone();
+        std::thread::spawn(move || handle_request(request, &event_loop_proxy, &config));
+    }
+}
+
+fn handle_request(
+    mut request: Request,
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    config: &Config,
+) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    // Names may be percent-encoded, e.g. /players/V%C3%A4in%C3%B6/move
+    let decoded = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8().map(|s| s.to_string()))
+        .collect::<Result<Vec<String>, _>>();
+    let decoded = match decoded {
+        Ok(decoded) => decoded,
+        Err(_) => {
+            respond(request, 400, "Invalid path");
+            return;
+        }
+    };
+    let segments = decoded.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    // Only recordings are large, everything else is a bit of json
+    let limit = match (request.method(), segments.as_slice()) {
+        (Method::Post, ["players", _, "audio"]) => MAX_AUDIO_BYTES,
+        _ => MAX_JSON_BYTES,
+    };
+    let mut body = vec![];
+    if request
+        .as_reader()
+        .take(limit as u64 + 1)
+        .read_to_end(&mut body)
+        .is_err()
+    {
+        respond(request, 400, "Failed to read body");
+        return;
+    }
+    if body.len() > limit {
+        respond(request, 413, "Body too large");
+        return;
+    }
+    let token = bearer_token(&request);
+    let (reply, receiver) = channel();
+    if let Some(event) = admin_event(request.method(), &segments, &body, reply) {
+        if token != config.admin_token {
+            respond_with_error(request, CommandError::Unauthorized);
+            return;
+        }
+        match event {
+            Ok(event) => {
+                send_event(event_loop_proxy, event);
+                respond_with_outcome(request, &receiver);
+            }
+            Err(e) => respond(request, 400, &e),
+        }
+        return;
+    }
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["state"]) => {
+            let (sender, receiver) = channel();
+            send_event(event_loop_proxy, CustomEvent::StateRequested(sender));
+            respond_with_reply(request, receiver);
+        }
+        (Method::Get, ["maze"]) => {
+            let (sender, receiver) = channel();
+            send_event(event_loop_proxy, CustomEvent::MazeRequested(sender));
+            respond_with_reply(request, receiver);
+        }
+        (Method::Get, ["stats"]) => {
+            let (sender, receiver) = channel();
+            send_event(event_loop_proxy, CustomEvent::StatsRequested(sender));
+            respond_with_reply(request, receiver);
+        }
+        (Method::Get, ["stats", name]) => {
+            let (sender, receiver) = channel();
+            send_event(event_loop_proxy, CustomEvent::StatsRequested(sender));
+            match receiver.recv_timeout(REPLY_TIMEOUT) {
+                Ok(leaderboard) => match leaderboard.iter().find(|p| p.name == *name) {
+                    Some(player) => respond_with_json(request, 200, player),
+                    None => {
+                        respond_with_error(request, CommandError::UnknownPlayer(name.to_string()))
+                    }
+                },
+                Err(_) => respond_with_error(request, CommandError::NoResponse),
+            }
+        }
+        (Method::Post, ["players"]) => match serde_json::from_slice::<NewPlayer>(&body) {
+            Ok(player) => {
+                if config.password.is_some() && player.password != config.password {
+                    respond_with_error(request, CommandError::Unauthorized);
+                    return;
+                }
+                if let Some(team) = player.team.as_ref().filter(|t| !is_valid_name(t)) {
+                    respond(request, 400, &format!("Invalid team name: {}", team));
+                    return;
+                }
+                let (reply, receiver) = channel();
+                send_event(
+                    event_loop_proxy,
+                    CustomEvent::PlayerConnected(player.name.clone(), reply),
+                );
+                let session = wait_reply(&receiver).and_then(|token| {
+                    if let Some(team) = player.team {
+                        let (reply, receiver) = channel();
+                        send_event(
+                            event_loop_proxy,
+                            CustomEvent::PlayerSetTeam(
+                                player.name,
+                                token.clone(),
+                                Some(team),
+                                reply,
+                            ),
+                        );
+                        wait_reply(&receiver)?;
+                    }
+                    Ok(token)
+                });
+                match session {
+                    Ok(token) => respond_with_json(request, 201, &Session { token }),
+                    Err(e) => respond_with_error(request, e),
+                }
+            }
+            Err(e) => respond(request, 400, &e.to_string()),
+        },
+        (Method::Post, ["players", "reconnect"]) => {
+            let (reply, receiver) = channel();
+            send_event(
+                event_loop_proxy,
+                CustomEvent::PlayerReconnected(token, reply),
+            );
+            match wait_reply(&receiver) {
+                Ok(name) => respond_with_json(request, 200, &Reconnected { name }),
+                Err(e) => respond_with_error(request, e),
+            }
+        }
+        (Method::Post, ["players", name, "move"]) => {
+            let (reply, receiver) = channel();
+            let event = serde_json::from_slice::<Move>(&body)
+                .map_err(|e| e.to_string())
+                .and_then(|m| m.into_event(name, token, &config.vocabulary, reply));
+            match event {
+                Ok(event) => {
+                    send_event(event_loop_proxy, event);
+                    respond_with_outcome(request, &receiver);
+                }
+                Err(e) => respond(request, 400, &e),
+            }
+        }
+        (Method::Post, ["players", name, "audio"]) => match Recording::decode(&body) {
+            Ok(recording) => {
+                let (reply, receiver) = channel();
+                send_event(
+                    event_loop_proxy,
+                    CustomEvent::PlayerAudio(
+                        name.to_string(),
+                        token,
+                        hear(&config.voices, name, recording),
+                        reply,
+                    ),
+                );
+                respond_with_outcome(request, &receiver);
+            }
+            Err(e) => respond(request, 400, &e),
+        },
+        (Method::Post, ["players", name, "enroll"]) => {
+            let (reply, receiver) = channel();
+            send_event(
+                event_loop_proxy,
+                CustomEvent::PlayerEnroll(name.to_string(), token, reply),
+            );
+            respond_with_outcome(request, &receiver);
+        }
+        (Method::Post, ["players", name, "ready"]) => {
+            let (reply, receiver) = channel();
+            send_event(
+                event_loop_proxy,
+                CustomEvent::PlayerReady(name.to_string(), token, reply),
+            );
+            respond_with_outcome(request, &receiver);
+        }
+        (Method::Post, ["players", name, "undo"]) => {
+            let undo = if body.is_empty() {
+                Ok(Undo { moves: None })
+            } else {
+                serde_json::from_slice::<Undo>(&body)
+            };
+            match undo {
+                Ok(undo) => {
+                    let (reply, receiver) = channel();
+                    let moves = undo.moves.unwrap_or(1).max(1);
+                    send_event(
+                        event_loop_proxy,
+                        CustomEvent::PlayerUndo(name.to_string(), token, moves, reply),
+                    );
+                    respond_with_outcome(request, &receiver);
+                }
+                Err(e) => respond(request, 400, &e.to_string()),
+            }
+        }
+        (Method::Put, ["players", name, "mode"]) => {
+            match serde_json::from_slice::<NewMode>(&body) {
+                Ok(m) => {
+                    let (reply, receiver) = channel();
+                    send_event(
+                        event_loop_proxy,
+                        CustomEvent::PlayerSetMode(name.to_string(), token, m.mode, reply),
+                    );
+                    respond_with_outcome(request, &receiver);
+                }
+                Err(e) => respond(request, 400, &e.to_string()),
+            }
+        }
+        (Method::Put, ["players", name, "team"]) => {
+            match serde_json::from_slice::<NewTeam>(&body) {
+                Ok(NewTeam { team: Some(team) }) if !is_valid_name(&team) => {
+                    respond(request, 400, &format!("Invalid team name: {}", team))
+                }
+                Ok(t) => {
+                    let (reply, receiver) = channel();
+                    send_event(
+                        event_loop_proxy,
+                        CustomEvent::PlayerSetTeam(name.to_string(), token, t.team, reply),
+                    );
+                    respond_with_outcome(request, &receiver);
+                }
+                Err(e) => respond(request, 400, &e.to_string()),
+            }
+        }
+        (Method::Delete, ["players", name]) => {
+            let (reply, receiver) = channel();
+            send_event(
+                event_loop_proxy,
+                CustomEvent::PlayerDisconnected(name.to_string(), token, reply),
+            );
+            respond_with_outcome(request, &receiver);
+        }
+        _ => respond(request, 404, "Not found"),
+    }
+}
+
+// None if the request isn't an admin request
+fn admin_event(
+    method: &Method,
+    segments: &[&str],
+    body: &[u8],
+    reply: Reply,
+) -> Option<Result<CustomEvent, String>> {
+    let event = match (method, segments) {
+        (Method::Post, ["restart"]) => Ok(CustomEvent::Regenerate(None, reply)),
+        (Method::Post, ["regenerate"]) => {
+            let size = if body.is_empty() {
+                Ok(Regenerate { size: None })
+            } else {
+                serde_json::from_slice::<Regenerate>(body)
+            };
+            match size {
+                Ok(Regenerate { size: Some(size) }) => {
+                    check_maze_size(size).map(|size| CustomEvent::Regenerate(Some(size), reply))
+                }
+                Ok(Regenerate { size: None }) => Ok(CustomEvent::Regenerate(None, reply)),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        (Method::Post, ["players", name, "kick"]) => {
+            Ok(CustomEvent::KickPlayer(name.to_string(), reply))
+        }
+        (Method::Post, ["players", name, "reset"]) => {
+            Ok(CustomEvent::ResetPlayer(name.to_string(), reply))
+        }
+        (Method::Put, ["players", name, "name"]) => serde_json::from_slice::<NewName>(body)
+            .map_err(|e| e.to_string())
+            .and_then(|new_name| {
+                if is_valid_name(&new_name.name) {
+                    Ok(CustomEvent::RenamePlayer(
+                        name.to_string(),
+                        new_name.name,
+                        reply,
+                    ))
+                } else {
+                    Err(format!("Invalid name: {}", new_name.name))
+                }
+            }),
+        (Method::Post, ["pause"]) => Ok(CustomEvent::SetPaused(true, reply)),
+        (Method::Post, ["resume"]) => Ok(CustomEvent::SetPaused(false, reply)),
+        (Method::Post, ["start"]) => Ok(CustomEvent::StartCountdown(reply)),
+        (Method::Post, ["bots"]) => serde_json::from_slice::<NewBot>(body)
+            .map_err(|e| e.to_string())
+            .and_then(|bot| {
+                if is_valid_name(&bot.name) {
+                    let level = bot.level.unwrap_or(BotLevel::Human);
+                    Ok(CustomEvent::AddBot(bot.name, level, reply))
+                } else {
+                    Err(format!("Invalid name: {}", bot.name))
+                }
+            }),
+        _ => return None,
+    };
+    Some(event)
+}
+
+// Session token from "Authorization: Bearer <token>", empty if missing
+fn bearer_token(request: &Request) -> String {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn send_event(event_loop_proxy: &EventLoopProxy<CustomEvent>, event: CustomEvent) {
+    event_loop_proxy.send_event(event).ok();
+}
+
+fn respond_with_reply<T: Serialize>(request: Request, receiver: Receiver<T>) {
+    match receiver.recv_timeout(REPLY_TIMEOUT) {
+        Ok(reply) => respond_with_json(request, 200, &reply),
+        Err(_) => respond_with_error(request, CommandError::NoResponse),
+    }
+}
+
+fn respond_with_outcome(request: Request, receiver: &Receiver<Result<String, CommandError>>) {
+    match wait_reply(receiver) {
+        Ok(message) => respond(request, 200, &message),
+        Err(e) => respond_with_error(request, e),
+    }
+}
+
+fn respond_with_json<T: Serialize>(request: Request, status: u16, body: &T) {
+    match serde_json::to_string(body) {
+        Ok(json) => {
+            let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+            request
+                .respond(
+                    Response::from_string(json)
+                        .with_status_code(status)
+                        .with_header(content_type),
+                )
+                .ok();
+        }
+        Err(e) => respond(request, 500, &e.to_string()),
+    }
+}
+
+fn respond_with_error(request: Request, error: CommandError) {
+    let status = match error {
+        CommandError::Unauthorized => 403,
+        CommandError::UnknownPlayer(_) => 404,
+        CommandError::NameTaken(_) => 409,
+        CommandError::InvalidName(_) => 400,
+        CommandError::TooFast(_) => 429,
+        CommandError::Blocked(_) => 422,
+        CommandError::LowConfidence(_) => 422,
+        CommandError::Unrecognized(_) => 422,
+        CommandError::NothingToUndo => 409,
+        CommandError::UndoLimit(_) => 409,
+        CommandError::Paused => 409,
+        CommandError::NotPlaying(_) => 409,
+        CommandError::AlreadyFinished => 409,
+        CommandError::NotYourTurn(_) => 409,
+        CommandError::NotYourDirection(_) => 409,
+        CommandError::NoResponse => 504,
+    };
+    respond(request, status, &error.to_string());
+}
+
+fn respond(request: Request, status: u16, message: &str) {
+    request
+        .respond(Response::from_string(message).with_status_code(status))
+        .ok();
+}
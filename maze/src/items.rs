@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+// Milliseconds a round of score attack lasts, unless a time limit is given
+pub const ROUND_LENGTH: f64 = 90000.;
+
+// One item per this many cells in score attack, unless the number of items is given
+pub const CELLS_PER_ITEM: i32 = 8;
+
+// Chance of an item being a gem instead of a coin
+pub const GEM_CHANCE: f64 = 0.2;
+
+// Picked up by the first player to enter its cell
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Item {
+    Coin,
+    Gem,
+}
+
+impl Item {
+    pub fn points(self) -> u32 {
+        match self {
+            Item::Coin => 1,
+            Item::Gem => 5,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::custom_events::is_valid_name;
+use crate::direction::Direction;
+use std::str::FromStr;
+use winit::event::VirtualKeyCode;
+
+// Keys a local (hot-seat) player steers with, one set per player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyBindings {
+    Arrows,
+    Wasd,
+    Ijkl,
+    Numpad,
+}
+
+// In the order they're handed out to local players given without bindings
+pub const KEY_BINDINGS: [KeyBindings; 4] = [
+    KeyBindings::Arrows,
+    KeyBindings::Wasd,
+    KeyBindings::Ijkl,
+    KeyBindings::Numpad,
+];
+
+impl KeyBindings {
+    pub fn keys(self) -> [(VirtualKeyCode, Direction); 4] {
+        match self {
+            KeyBindings::Arrows => [
+                (VirtualKeyCode::Up, Direction::Up),
+                (VirtualKeyCode::Right, Direction::Right),
+                (VirtualKeyCode::Down, Direction::Down),
+                (VirtualKeyCode::Left, Direction::Left),
+            ],
+            KeyBindings::Wasd => [
+                (VirtualKeyCode::W, Direction::Up),
+                (VirtualKeyCode::D, Direction::Right),
+                (VirtualKeyCode::S, Direction::Down),
+                (VirtualKeyCode::A, Direction::Left),
+            ],
+            KeyBindings::Ijkl => [
+                (VirtualKeyCode::I, Direction::Up),
+                (VirtualKeyCode::L, Direction::Right),
+                (VirtualKeyCode::K, Direction::Down),
+                (VirtualKeyCode::J, Direction::Left),
+            ],
+            KeyBindings::Numpad => [
+                (VirtualKeyCode::Numpad8, Direction::Up),
+                (VirtualKeyCode::Numpad6, Direction::Right),
+                (VirtualKeyCode::Numpad2, Direction::Down),
+                (VirtualKeyCode::Numpad4, Direction::Left),
+            ],
+        }
+    }
+}
+
+impl FromStr for KeyBindings {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arrows" => Ok(KeyBindings::Arrows),
+            "wasd" => Ok(KeyBindings::Wasd),
+            "ijkl" => Ok(KeyBindings::Ijkl),
+            "numpad" => Ok(KeyBindings::Numpad),
+            _ => Err(format!("Invalid key bindings: {}", s)),
+        }
+    }
+}
+
+// A player sitting at the game's own keyboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalPlayer {
+    pub name: String,
+    pub bindings: KeyBindings,
+}
+
+impl LocalPlayer {
+    // "okko=wasd", or just "okko" to get the first bindings not taken by others
+    pub fn parse(s: &str, taken: &[LocalPlayer]) -> Result<LocalPlayer, String> {
+        let (name, bindings) = match s.split_once('=') {
+            Some((name, bindings)) => (name, bindings.parse::<KeyBindings>()?),
+            None => {
+                let free = KEY_BINDINGS
+                    .iter()
+                    .find(|b| !taken.iter().any(|p| p.bindings == **b))
+                    .ok_or("No key bindings left for another local player")?;
+                (s, *free)
+            }
+        };
+        if !is_valid_name(name) {
+            return Err(format!("Invalid local player name: {}", name));
+        }
+        if taken.iter().any(|p| p.name == name) {
+            return Err(format!("Local player {} given twice", name));
+        }
+        if taken.iter().any(|p| p.bindings == bindings) {
+            return Err(format!("Key bindings {:?} given twice", bindings));
+        }
+        Ok(LocalPlayer {
+            name: name.to_string(),
+            bindings,
+        })
+    }
+}
@@ -0,0 +1,352 @@
+use crate::direction::Steering;
+use crate::mfcc::{Audio, Recording};
+use crate::vocabulary::Vocabulary;
+use log::error;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+// Words a player records when enrolling their voice, each this many times
+pub const ENROLLMENT_WORDS: [&str; 4] = ["up", "down", "left", "right"];
+pub const ENROLLMENT_TAKES: usize = 3;
+
+// A recording of a command word, as mfcc frames
+#[derive(Debug, Clone)]
+pub struct Template {
+    word: String,
+    features: Vec<Vec<f32>>,
+}
+
+// Recognizes command words by comparing recordings against enrolled templates with
+// dynamic time warping, so no model training is needed
+#[derive(Debug, Clone, Default)]
+pub struct KeywordSpotter {
+    templates: Vec<Template>,
+}
+
+impl KeywordSpotter {
+    // Loads wav files named after their word, e.g. up_1.wav, left2.wav or vasen_1.wav (subdirectories
+    // are skipped). File names go through the vocabulary, so they can be in any language it knows.
+    pub fn load(dir: &Path, vocabulary: &Vocabulary) -> Result<KeywordSpotter, String> {
+        let mut spotter = KeywordSpotter::default();
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read templates {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if !path.is_file() || path.extension().and_then(|e| e.to_str()) != Some("wav") {
+                continue;
+            }
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            let word = stem
+                .split(|c: char| !c.is_alphabetic())
+                .next()
+                .unwrap_or("");
+            if word.is_empty() {
+                continue;
+            }
+            let bytes = fs::read(&path)
+                .map_err(|e| format!("Failed to read template {}: {}", path.display(), e))?;
+            let audio = Audio::decode(&bytes)
+                .map_err(|e| format!("Invalid template {}: {}", path.display(), e))?;
+            spotter.add(&vocabulary.translate(word), audio.mfcc());
+        }
+        Ok(spotter)
+    }
+
+    // False if the recording is too short or silent to be a template
+    pub fn add(&mut self, word: &str, features: Vec<Vec<f32>>) -> bool {
+        if features.is_empty() {
+            return false;
+        }
+        self.templates.push(Template {
+            word: word.to_string(),
+            features,
+        });
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    // Recognizes a recording's features as ranked move candidates with confidences
+    pub fn recognize(&self, features: &[Vec<f32>]) -> Result<Vec<(Steering, f32)>, String> {
+        if self.is_empty() {
+            return Err("No voice templates enrolled".to_string());
+        }
+        let candidates = self
+            .classify(features)
+            .into_iter()
+            .filter_map(|(word, confidence)| Some((word.parse::<Steering>().ok()?, confidence)))
+            .collect::<Vec<(Steering, f32)>>();
+        if candidates.is_empty() {
+            Err("Could not recognize a command".to_string())
+        } else {
+            Ok(candidates)
+        }
+    }
+
+    // Words ranked by how close their nearest template is, with confidences summing to 1
+    pub fn classify(&self, features: &[Vec<f32>]) -> Vec<(String, f32)> {
+        if features.is_empty() {
+            return vec![];
+        }
+        let mut nearest: Vec<(String, f32)> = vec![];
+        for template in self.templates.iter() {
+            let distance = dtw_distance(features, &template.features);
+            match nearest.iter_mut().find(|(word, _)| *word == template.word) {
+                Some((_, d)) => *d = d.min(distance),
+                None => nearest.push((template.word.clone(), distance)),
+            }
+        }
+        let total = nearest
+            .iter()
+            .map(|(_, d)| 1. / d.max(f32::EPSILON))
+            .sum::<f32>();
+        let mut ranked = nearest
+            .into_iter()
+            .map(|(word, d)| (word, 1. / d.max(f32::EPSILON) / total))
+            .collect::<Vec<(String, f32)>>();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        ranked
+    }
+}
+
+// Shared templates (from --templates) plus the ones each player has enrolled, by player name
+#[derive(Debug, Clone, Default)]
+pub struct Voices {
+    shared: Arc<KeywordSpotter>,
+    players: HashMap<String, Arc<KeywordSpotter>>,
+    // Recordings of enrollments in progress, the player's old templates are used until theirs is done
+    enrolling: HashMap<String, Vec<(String, Recording)>>,
+    // Enrolled recordings are saved as <dir>/<player>/<word>_<take>.wav and loaded on the next start
+    dir: Option<PathBuf>,
+}
+
+impl Voices {
+    pub fn load(dir: &Path, vocabulary: &Vocabulary) -> Result<Voices, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create templates {}: {}", dir.display(), e))?;
+        let mut voices = Voices {
+            shared: Arc::new(KeywordSpotter::load(dir, vocabulary)?),
+            players: HashMap::new(),
+            enrolling: HashMap::new(),
+            dir: Some(dir.to_path_buf()),
+        };
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read templates {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if let (true, Some(name)) = (path.is_dir(), path.file_name().and_then(|n| n.to_str())) {
+                let spotter = KeywordSpotter::load(&path, vocabulary)?;
+                voices.players.insert(name.to_string(), Arc::new(spotter));
+            }
+        }
+        Ok(voices)
+    }
+
+    // Player's own templates when they have enrolled, shared ones otherwise
+    pub fn spotter(&self, player: &str) -> Arc<KeywordSpotter> {
+        match self.players.get(player) {
+            Some(spotter) if !spotter.is_empty() => spotter.clone(),
+            _ => self.shared.clone(),
+        }
+    }
+
+    pub fn is_enrolling(&self, player: &str) -> bool {
+        self.enrolling.contains_key(player)
+    }
+
+    // Starts recording player's templates from scratch, an earlier unfinished enrollment is dropped
+    pub fn start_enrollment(&mut self, player: &str) {
+        self.enrolling.insert(player.to_string(), vec![]);
+    }
+
+    pub fn cancel_enrollment(&mut self, player: &str) {
+        self.enrolling.remove(player);
+    }
+
+    // Player's templates follow them to their new name
+    pub fn rename(&mut self, player: &str, new_name: &str) {
+        if let Some(spotter) = self.players.remove(player) {
+            self.players.insert(new_name.to_string(), spotter);
+        }
+        if let Some(recordings) = self.enrolling.remove(player) {
+            self.enrolling.insert(new_name.to_string(), recordings);
+        }
+        if let (Some(dir), Some(new_dir)) = (self.player_dir(player), self.player_dir(new_name)) {
+            if dir.exists() {
+                fs::rename(&dir, &new_dir)
+                    .unwrap_or_else(|e| error!("Failed to move {}: {}", dir.display(), e));
+            }
+        }
+    }
+
+    pub fn enroll(
+        &mut self,
+        player: &str,
+        word: &str,
+        recording: &Recording,
+    ) -> Result<(), String> {
+        if recording.features.is_empty() {
+            return Err("Recording is too short or silent".to_string());
+        }
+        self.enrolling
+            .entry(player.to_string())
+            .or_default()
+            .push((word.to_string(), recording.clone()));
+        Ok(())
+    }
+
+    // Replaces player's templates with the ones just enrolled, also on disk
+    pub fn finish_enrollment(&mut self, player: &str) {
+        let recordings = self.enrolling.remove(player).unwrap_or_default();
+        let mut spotter = KeywordSpotter::default();
+        for (word, recording) in recordings.iter() {
+            spotter.add(word, recording.features.clone());
+        }
+        self.players.insert(player.to_string(), Arc::new(spotter));
+        if let Some(dir) = self.player_dir(player) {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)
+                    .unwrap_or_else(|e| error!("Failed to remove {}: {}", dir.display(), e));
+            }
+            for (i, (word, recording)) in recordings.iter().enumerate() {
+                let take = recordings[..=i].iter().filter(|(w, _)| w == word).count();
+                let path = dir.join(format!("{}_{}.wav", word, take));
+                fs::create_dir_all(&dir)
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| recording.audio.save(&path))
+                    .unwrap_or_else(|e| error!("Failed to save {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    // None without a templates dir, or if the name isn't safe as a directory name
+    fn player_dir(&self, player: &str) -> Option<PathBuf> {
+        let safe = player
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        match &self.dir {
+            Some(dir) if safe && !player.is_empty() => Some(dir.join(player)),
+            _ => None,
+        }
+    }
+}
+
+// A player's recording as the client's thread made sense of it
+#[derive(Debug, Clone)]
+pub enum Utterance {
+    // Player is enrolling, the recording becomes a template
+    Template(Recording),
+    // Recognized move candidates, or why there were none
+    Candidates(Result<Vec<(Steering, f32)>, String>),
+}
+
+// Matches the recording on the client's thread, so the game loop only gets the result. The
+// templates are matched outside the lock, so others enrolling meanwhile aren't held up.
+pub fn hear(voices: &RwLock<Voices>, player: &str, recording: Recording) -> Utterance {
+    let (enrolling, spotter) = {
+        let voices = voices.read().unwrap_or_else(PoisonError::into_inner);
+        (voices.is_enrolling(player), voices.spotter(player))
+    };
+    if enrolling {
+        Utterance::Template(recording)
+    } else {
+        Utterance::Candidates(spotter.recognize(&recording.features))
+    }
+}
+
+// Where a player is in recording their templates
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Enrollment {
+    word: usize,
+    take: usize,
+}
+
+impl Enrollment {
+    pub fn word(&self) -> &'static str {
+        ENROLLMENT_WORDS[self.word]
+    }
+
+    // None once every word has been recorded
+    pub fn next(self) -> Option<Enrollment> {
+        if self.take + 1 < ENROLLMENT_TAKES {
+            Some(Enrollment {
+                take: self.take + 1,
+                ..self
+            })
+        } else if self.word + 1 < ENROLLMENT_WORDS.len() {
+            Some(Enrollment {
+                word: self.word + 1,
+                take: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    // E.g. "left 2/3"
+    pub fn prompt(&self) -> String {
+        format!("{} {}/{}", self.word(), self.take + 1, ENROLLMENT_TAKES)
+    }
+}
+
+// Cost of the cheapest alignment of two feature sequences, normalized by their lengths
+fn dtw_distance(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    let mut previous = vec![f32::INFINITY; b.len() + 1];
+    let mut current = vec![f32::INFINITY; b.len() + 1];
+    previous[0] = 0.;
+    for frame_a in a.iter() {
+        current[0] = f32::INFINITY;
+        for (j, frame_b) in b.iter().enumerate() {
+            let cost = frame_a
+                .iter()
+                .zip(frame_b.iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt();
+            current[j + 1] = cost + previous[j].min(previous[j + 1]).min(current[j]);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()] / (a.len() + b.len()) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(values: &[f32]) -> Vec<Vec<f32>> {
+        values.iter().map(|v| vec![*v, -*v]).collect()
+    }
+
+    #[test]
+    fn identical_sequences_are_closest() {
+        let a = frames(&[1., 2., 3., 2.]);
+        assert_eq!(dtw_distance(&a, &a), 0.);
+        // Warping absorbs a slower take of the same word
+        assert_eq!(dtw_distance(&a, &frames(&[1., 1., 2., 3., 3., 2.])), 0.);
+        assert!(dtw_distance(&a, &frames(&[3., 2., 1., 2.])) > 0.);
+    }
+
+    #[test]
+    fn empty_sequence_is_infinitely_far() {
+        let a = frames(&[1., 2.]);
+        assert_eq!(dtw_distance(&a, &[]), f32::INFINITY);
+        assert_eq!(dtw_distance(&[], &a), f32::INFINITY);
+    }
+
+    #[test]
+    fn classifies_nearest_word() {
+        let mut spotter = KeywordSpotter::default();
+        assert!(!spotter.add("up", vec![]));
+        spotter.add("up", frames(&[1., 2., 3.]));
+        spotter.add("down", frames(&[3., 2., 1.]));
+        let ranked = spotter.classify(&frames(&[1., 2., 2., 3.]));
+        assert_eq!(ranked[0].0, "up");
+        assert!(ranked[0].1 > ranked[1].1);
+        assert!(spotter.classify(&[]).is_empty());
+    }
+}
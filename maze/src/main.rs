@@ -1,29 +1,51 @@
 mod app;
+mod bots;
+mod config;
+mod coop;
 mod custom_events;
 mod direction;
 mod game;
 mod gui;
+mod http;
+mod items;
+mod keyboard;
+mod keyword_spotting;
 mod maze;
+mod mfcc;
+mod monsters;
+mod server;
+mod state;
+mod stats;
+mod tag;
+mod teams;
+mod tournament;
+mod vocabulary;
 mod window;
 
 use app::App;
+use config::Config;
 use log::error;
 use pixels::Error;
+use server::Listener;
 use std::env;
 use std::process::exit;
 
 pub fn main() -> Result<(), Error> {
     env_logger::init();
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        2 => {
-            let maze_size = args[1].parse::<i32>().expect("Invalid maze size");
-            if maze_size < 0 || maze_size > 50 {
-                error!("Invalid maze size, can't be less than 0 or larger than 50");
-                exit(0);
-            }
-            App::build().run("Maze Craze", 1280, 720, maze_size)
-        }
-        _ => App::build().run("Maze Craze", 1280, 720, 16),
-    }
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = Config::from_args(&args).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    });
+    // Bind everything before opening the window, so a taken port is reported right away
+    let listeners = config
+        .listeners
+        .iter()
+        .map(Listener::bind)
+        .collect::<Result<Vec<Listener>, String>>()
+        .unwrap_or_else(|e| {
+            error!("{}", e);
+            exit(1);
+        });
+    App::build().run("Maze Craze", 1280, 720, config, listeners)
 }
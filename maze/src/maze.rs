@@ -1,16 +1,19 @@
 use crate::direction::Direction;
+use crate::items::{Item, GEM_CHANCE};
 use euclid::Vector2D;
 use log::error;
-use rand;
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use raqote::Color;
+use std::collections::VecDeque;
 
 #[derive(Debug, Clone)]
 pub struct Cell {
     pos: Vector2D<i32, i32>,
     available_directions: Vec<Direction>,
     color: Color,
+    item: Option<Item>,
 }
 
 impl Cell {
@@ -19,6 +22,7 @@ impl Cell {
             pos: Vector2D::<i32, i32>::new(x, y),
             color: Color::new(255, 100, 100, 100),
             available_directions: vec![],
+            item: None,
         }
     }
 
@@ -66,6 +70,10 @@ impl Cell {
     pub fn available_directions_mut(&mut self) -> &mut Vec<Direction> {
         &mut self.available_directions
     }
+
+    pub fn item(&self) -> Option<Item> {
+        self.item
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -74,10 +82,12 @@ pub struct MazeGrid {
     size: i32,
     start: (i32, i32),
     end: (i32, i32),
+    // Same size & seed generate the same maze
+    seed: u64,
 }
 
 impl MazeGrid {
-    pub fn new(size: i32, start_pos: (i32, i32), end_pos: (i32, i32)) -> MazeGrid {
+    pub fn new(size: i32, start_pos: (i32, i32), end_pos: (i32, i32), seed: u64) -> MazeGrid {
         let mut grid = vec![];
         for y in 0..size {
             let mut row = vec![];
@@ -91,10 +101,35 @@ impl MazeGrid {
             size,
             start: start_pos,
             end: end_pos,
+            seed,
         }
         .generate_maze(start_pos, end_pos)
     }
 
+    // Maze with just the given passages, each opening a cell's wall in a direction
+    #[cfg(test)]
+    pub fn with_passages(
+        size: i32,
+        start: (i32, i32),
+        end: (i32, i32),
+        passages: &[((i32, i32), Direction)],
+    ) -> MazeGrid {
+        let mut maze = MazeGrid {
+            grid: (0..size)
+                .map(|y| (0..size).map(|x| Cell::new(x, y)).collect())
+                .collect(),
+            size,
+            start,
+            end,
+            seed: 0,
+        };
+        for ((x, y), dir) in passages.iter() {
+            let (dir_x, dir_y) = dir.grid_dir();
+            maze.cell_link_to(*x, *y, x + dir_x, y + dir_y, *dir);
+        }
+        maze
+    }
+
     fn generate_maze(mut self, start: (i32, i32), end_pos: (i32, i32)) -> Self {
         if start.0 >= 0
             && start.0 < self.size
@@ -105,7 +140,8 @@ impl MazeGrid {
             && end_pos.1 >= 0
             && end_pos.1 < self.size
         {
-            self.generate_from(start.0, start.1);
+            let mut rng = StdRng::seed_from_u64(self.seed);
+            self.generate_from(start.0, start.1, &mut rng);
             self.grid[start.1 as usize][start.0 as usize].set_color(Color::new(255, 0, 255, 0));
             self.grid[end_pos.1 as usize][end_pos.0 as usize].set_color(Color::new(255, 255, 0, 0));
         } else {
@@ -114,21 +150,20 @@ impl MazeGrid {
         self
     }
 
-    fn generate_from(&mut self, cur_x: i32, cur_y: i32) {
-        let mut directions = vec![
+    fn generate_from(&mut self, cur_x: i32, cur_y: i32, rng: &mut StdRng) {
+        let mut directions = [
             Direction::Up,
             Direction::Right,
             Direction::Down,
             Direction::Left,
         ];
-        let mut rng = thread_rng();
-        directions.shuffle(&mut rng);
+        directions.shuffle(rng);
         for dir in directions.iter() {
             let (dir_x, dir_y) = dir.grid_dir();
             let (new_x, new_y) = (cur_x + dir_x, cur_y + dir_y);
             if self.cell_is_unvisited(new_x, new_y) {
                 self.cell_link_to(cur_x, cur_y, new_x, new_y, *dir);
-                self.generate_from(new_x, new_y);
+                self.generate_from(new_x, new_y, rng);
             }
         }
     }
@@ -144,14 +179,14 @@ impl MazeGrid {
     ) {
         let n_vec = self.grid[cell_to_y as usize][cell_to_x as usize].available_directions_mut();
         n_vec.push(dir.opposite());
-        n_vec.sort_by(|a, b| a.to_int().cmp(&b.to_int()));
+        n_vec.sort_by_key(|a| a.to_int());
         n_vec.dedup();
         let c_vec = self
             .cell_mut_at(cell_x, cell_y)
             .unwrap()
             .available_directions_mut();
         c_vec.push(dir);
-        c_vec.sort_by(|a, b| a.to_int().cmp(&b.to_int()));
+        c_vec.sort_by_key(|a| a.to_int());
         c_vec.dedup();
     }
 
@@ -162,8 +197,7 @@ impl MazeGrid {
             && y < self.size
             && self.grid[y as usize][x as usize]
                 .available_directions()
-                .len()
-                == 0
+                .is_empty()
     }
 
     pub fn end_pos(&self) -> Vector2D<i32, i32> {
@@ -182,7 +216,14 @@ impl MazeGrid {
         }
     }
 
-    #[allow(dead_code)]
+    // Neighbouring cell in given direction, if there's a passage to it
+    pub fn linked_cell(&self, pos: Vector2D<i32, i32>, dir: Direction) -> Option<&Cell> {
+        let (dir_x, dir_y) = dir.grid_dir();
+        let cell = self.cell_at(pos.x, pos.y)?;
+        self.cell_at(pos.x + dir_x, pos.y + dir_y)
+            .filter(|other| cell.has_link_to(other))
+    }
+
     pub fn cell_mut_at(&mut self, x: i32, y: i32) -> Option<&mut Cell> {
         if x < 0 || x >= self.size || y < 0 || y >= self.size {
             None
@@ -191,7 +232,80 @@ impl MazeGrid {
         }
     }
 
+    // Scatters items on cells other than start & end, the same cells for the same seed
+    pub fn place_items(&mut self, count: usize) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (start, end) = (self.start_pos(), self.end_pos());
+        let mut cells = self
+            .grid
+            .iter()
+            .flatten()
+            .map(|cell| cell.pos())
+            .filter(|pos| *pos != start && *pos != end)
+            .collect::<Vec<Vector2D<i32, i32>>>();
+        cells.shuffle(&mut rng);
+        for pos in cells.into_iter().take(count) {
+            let item = if rng.gen_bool(GEM_CHANCE) {
+                Item::Gem
+            } else {
+                Item::Coin
+            };
+            self.grid[pos.y as usize][pos.x as usize].item = Some(item);
+        }
+    }
+
+    pub fn take_item(&mut self, pos: Vector2D<i32, i32>) -> Option<Item> {
+        self.cell_mut_at(pos.x, pos.y)?.item.take()
+    }
+
+    // Cells with an item still on them
+    pub fn items(&self) -> Vec<Vector2D<i32, i32>> {
+        self.grid
+            .iter()
+            .flatten()
+            .filter(|cell| cell.item.is_some())
+            .map(|cell| cell.pos())
+            .collect()
+    }
+
+    // First move of a shortest path from pos to the nearest of the targets (breadth first),
+    // None if already at one or none can be reached
+    pub fn first_step(
+        &self,
+        from: Vector2D<i32, i32>,
+        targets: &[Vector2D<i32, i32>],
+    ) -> Option<Direction> {
+        if targets.contains(&from) {
+            return None;
+        }
+        let mut visited = vec![vec![false; self.size as usize]; self.size as usize];
+        visited[from.y as usize][from.x as usize] = true;
+        let mut queue = VecDeque::new();
+        queue.push_back((from, None));
+        while let Some((pos, first)) = queue.pop_front() {
+            for dir in (0..4).map(Direction::new) {
+                if let Some(cell) = self.linked_cell(pos, dir) {
+                    let next = cell.pos();
+                    if visited[next.y as usize][next.x as usize] {
+                        continue;
+                    }
+                    visited[next.y as usize][next.x as usize] = true;
+                    let first = first.or(Some(dir));
+                    if targets.contains(&next) {
+                        return first;
+                    }
+                    queue.push_back((next, first));
+                }
+            }
+        }
+        None
+    }
+
     pub fn size(&self) -> i32 {
         self.size
     }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 }
@@ -0,0 +1,266 @@
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::f32::consts::PI;
+use std::io::Cursor;
+use std::path::Path;
+
+// Raw pcm (anything not starting with a wav header) is expected to be 16 bit little endian mono at this rate
+pub const RAW_PCM_SAMPLE_RATE: u32 = 16000;
+const FRAME_MS: f32 = 25.;
+const HOP_MS: f32 = 10.;
+const PRE_EMPHASIS: f32 = 0.97;
+const MEL_FILTERS: usize = 26;
+const MIN_FREQUENCY: f32 = 20.;
+const MAX_FREQUENCY: f32 = 8000.;
+// Coefficients kept after dropping the 0th (overall loudness)
+const COEFFICIENTS: usize = 12;
+// Frames quieter than this (relative to the loudest frame) are trimmed from the start & end
+const SILENCE_DB: f32 = 30.;
+// A command word takes a second or two, longer recordings are rejected
+pub const MAX_RECORDING_SECONDS: u32 = 10;
+
+#[derive(Debug, Clone)]
+pub struct Audio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Audio {
+    // Wav (any bit depth, channels are mixed down) or raw pcm
+    pub fn decode(bytes: &[u8]) -> Result<Audio, String> {
+        if bytes.starts_with(b"RIFF") {
+            let reader = hound::WavReader::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+            let spec = reader.spec();
+            let samples = match spec.sample_format {
+                hound::SampleFormat::Int => {
+                    let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                    reader
+                        .into_samples::<i32>()
+                        .map(|s| s.map(|s| s as f32 / scale))
+                        .collect::<Result<Vec<f32>, hound::Error>>()
+                }
+                hound::SampleFormat::Float => reader.into_samples::<f32>().collect(),
+            }
+            .map_err(|e| e.to_string())?;
+            let channels = spec.channels.max(1) as usize;
+            Ok(Audio {
+                samples: samples
+                    .chunks(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+                    .collect(),
+                sample_rate: spec.sample_rate,
+            })
+        } else {
+            Ok(Audio {
+                samples: bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.)
+                    .collect(),
+                sample_rate: RAW_PCM_SAMPLE_RATE,
+            })
+        }
+    }
+
+    pub fn seconds(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate.max(1) as f32
+    }
+
+    // As 16 bit mono wav
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).map_err(|e| e.to_string())?;
+        for sample in self.samples.iter() {
+            let sample = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+            writer.write_sample(sample).map_err(|e| e.to_string())?;
+        }
+        writer.finalize().map_err(|e| e.to_string())
+    }
+
+    // Mel frequency cepstral coefficients per frame, leading & trailing silence trimmed and
+    // normalized to zero mean (cepstral mean normalization) to take out microphone differences
+    pub fn mfcc(&self) -> Vec<Vec<f32>> {
+        let frame_len = (self.sample_rate as f32 * FRAME_MS / 1000.) as usize;
+        let hop = (self.sample_rate as f32 * HOP_MS / 1000.) as usize;
+        if hop == 0 || self.samples.len() < frame_len {
+            return vec![];
+        }
+        let n_fft = frame_len.next_power_of_two();
+        let emphasized = self
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s - PRE_EMPHASIS * if i > 0 { self.samples[i - 1] } else { 0. })
+            .collect::<Vec<f32>>();
+        let window = (0..frame_len)
+            .map(|i| 0.54 - 0.46 * (2. * PI * i as f32 / (frame_len - 1) as f32).cos())
+            .collect::<Vec<f32>>();
+        let filters = mel_filterbank(n_fft, self.sample_rate);
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(n_fft);
+        let mut frames = vec![];
+        let mut energies = vec![];
+        for start in (0..=self.samples.len() - frame_len).step_by(hop) {
+            let mut buffer = (0..n_fft)
+                .map(|i| {
+                    if i < frame_len {
+                        Complex::new(emphasized[start + i] * window[i], 0.)
+                    } else {
+                        Complex::new(0., 0.)
+                    }
+                })
+                .collect::<Vec<Complex<f32>>>();
+            fft.process(&mut buffer);
+            let power = buffer[..=n_fft / 2]
+                .iter()
+                .map(|c| c.norm_sqr() / n_fft as f32)
+                .collect::<Vec<f32>>();
+            energies.push(power.iter().sum::<f32>().max(1e-10).log10() * 10.);
+            let log_mel = filters
+                .iter()
+                .map(|filter| {
+                    let e = filter
+                        .iter()
+                        .zip(power.iter())
+                        .map(|(w, p)| w * p)
+                        .sum::<f32>();
+                    e.max(1e-10).ln()
+                })
+                .collect::<Vec<f32>>();
+            frames.push(dct(&log_mel));
+        }
+        let loudest = energies.iter().cloned().fold(f32::MIN, f32::max);
+        let voiced = |e: &f32| *e > loudest - SILENCE_DB;
+        let first = energies.iter().position(voiced).unwrap_or(0);
+        let last = energies.iter().rposition(voiced).unwrap_or(0);
+        let mut frames = frames[first..=last].to_vec();
+        let mean = (0..COEFFICIENTS)
+            .map(|c| frames.iter().map(|f| f[c]).sum::<f32>() / frames.len() as f32)
+            .collect::<Vec<f32>>();
+        for frame in frames.iter_mut() {
+            for (c, value) in frame.iter_mut().enumerate() {
+                *value -= mean[c];
+            }
+        }
+        frames
+    }
+}
+
+// A decoded recording with its mfcc features, worked out on the client's thread so the game loop
+// only has to compare them. The audio is kept for saving enrolled templates.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub audio: Audio,
+    pub features: Vec<Vec<f32>>,
+}
+
+impl Recording {
+    pub fn decode(bytes: &[u8]) -> Result<Recording, String> {
+        let audio = Audio::decode(bytes)?;
+        if audio.seconds() > MAX_RECORDING_SECONDS as f32 {
+            return Err(format!(
+                "Recording is longer than {} seconds",
+                MAX_RECORDING_SECONDS
+            ));
+        }
+        let features = audio.mfcc();
+        Ok(Recording { audio, features })
+    }
+}
+
+// Triangular filters spaced evenly on the mel scale, as weights per fft bin
+fn mel_filterbank(n_fft: usize, sample_rate: u32) -> Vec<Vec<f32>> {
+    let to_mel = |f: f32| 2595. * (1. + f / 700.).log10();
+    let to_hz = |m: f32| 700. * (10f32.powf(m / 2595.) - 1.);
+    let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.);
+    let (min_mel, max_mel) = (to_mel(MIN_FREQUENCY), to_mel(max_frequency));
+    let bins = (0..MEL_FILTERS + 2)
+        .map(|i| {
+            let hz = to_hz(min_mel + (max_mel - min_mel) * i as f32 / (MEL_FILTERS + 1) as f32);
+            hz * n_fft as f32 / sample_rate as f32
+        })
+        .collect::<Vec<f32>>();
+    (0..MEL_FILTERS)
+        .map(|m| {
+            let (left, center, right) = (bins[m], bins[m + 1], bins[m + 2]);
+            (0..=n_fft / 2)
+                .map(|k| {
+                    let k = k as f32;
+                    if k > left && k <= center {
+                        (k - left) / (center - left)
+                    } else if k > center && k < right {
+                        (right - k) / (right - center)
+                    } else {
+                        0.
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// DCT-II of log mel energies, skipping the 0th coefficient
+fn dct(log_mel: &[f32]) -> Vec<f32> {
+    let n = log_mel.len() as f32;
+    (1..=COEFFICIENTS)
+        .map(|k| {
+            log_mel
+                .iter()
+                .enumerate()
+                .map(|(i, e)| e * (PI * k as f32 * (i as f32 + 0.5) / n).cos())
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stereo 16 bit wav with the channels' samples interleaved
+    fn wav(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        for sample in samples.iter() {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn decodes_wav() {
+        let samples = [16384, -16384, 16384, 0].repeat(4000);
+        let audio = Audio::decode(&wav(8000, &samples)).unwrap();
+        assert_eq!(audio.sample_rate, 8000);
+        assert_eq!(audio.seconds(), 1.);
+        assert_eq!(&audio.samples[..2], &[0., 0.25]);
+    }
+
+    #[test]
+    fn decodes_raw_pcm() {
+        let bytes = [0x00, 0x40, 0x00, 0xc0].repeat(RAW_PCM_SAMPLE_RATE as usize / 2);
+        let audio = Audio::decode(&bytes).unwrap();
+        assert_eq!(audio.sample_rate, RAW_PCM_SAMPLE_RATE);
+        assert_eq!(audio.seconds(), 1.);
+        assert_eq!(&audio.samples[..2], &[0.5, -0.5]);
+    }
+
+    #[test]
+    fn rejects_long_recordings() {
+        let seconds = |s: u32| vec![0; (RAW_PCM_SAMPLE_RATE * s * 2) as usize];
+        assert!(Recording::decode(&seconds(MAX_RECORDING_SECONDS)).is_ok());
+        assert!(Recording::decode(&seconds(MAX_RECORDING_SECONDS + 1)).is_err());
+        let samples = vec![0; (8000 * (MAX_RECORDING_SECONDS + 1) * 2) as usize];
+        assert!(Recording::decode(&wav(8000, &samples)).is_err());
+    }
+}
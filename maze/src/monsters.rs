@@ -0,0 +1,178 @@
+use crate::direction::Direction;
+use crate::maze::MazeGrid;
+use euclid::Vector2D;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+
+// Points a player loses when caught, with the points penalty
+pub const MONSTER_POINTS: u32 = 3;
+
+// Hard monsters go after players this many cells away or closer, counting rows & columns
+// (Manhattan distance, walls aside)
+pub const HUNT_RANGE: i32 = 4;
+
+// How fast monsters move, and whether they hunt players down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonsterLevel {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl MonsterLevel {
+    // Milliseconds between moves
+    pub fn interval(self) -> f64 {
+        match self {
+            MonsterLevel::Easy => 1000.,
+            MonsterLevel::Normal => 600.,
+            MonsterLevel::Hard => 400.,
+        }
+    }
+
+    pub fn hunts(self) -> bool {
+        self == MonsterLevel::Hard
+    }
+}
+
+impl FromStr for MonsterLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(MonsterLevel::Easy),
+            "normal" => Ok(MonsterLevel::Normal),
+            "hard" => Ok(MonsterLevel::Hard),
+            _ => Err(format!("Invalid monster level: {}", s)),
+        }
+    }
+}
+
+// What happens to a player a monster catches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Penalty {
+    // Back to start
+    Start,
+    // Loses some of the points of collected items
+    Points,
+}
+
+impl FromStr for Penalty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" => Ok(Penalty::Start),
+            "points" => Ok(Penalty::Points),
+            _ => Err(format!("Invalid monster penalty: {}", s)),
+        }
+    }
+}
+
+// Patrols the maze by following a wall, every other monster with the left hand
+#[derive(Debug, Clone)]
+pub struct Monster {
+    pub pos: Vector2D<i32, i32>,
+    heading: Direction,
+    right_hand: bool,
+    // Milliseconds until the next move
+    cooldown: f64,
+}
+
+impl Monster {
+    // Monsters start in the far half of the maze from the start, on the same cells for the same seed
+    pub fn spawn(maze: &MazeGrid, count: usize) -> Vec<Monster> {
+        let mut rng = StdRng::seed_from_u64(maze.seed());
+        let (start, end) = (maze.start_pos(), maze.end_pos());
+        let mut cells = vec![];
+        for y in 0..maze.size() {
+            for x in 0..maze.size() {
+                let far = (x - start.x).abs() + (y - start.y).abs() >= maze.size();
+                if far && Vector2D::new(x, y) != end {
+                    cells.push(Vector2D::new(x, y));
+                }
+            }
+        }
+        cells.shuffle(&mut rng);
+        cells
+            .into_iter()
+            .take(count)
+            .enumerate()
+            .map(|(i, pos)| Monster {
+                pos,
+                heading: Direction::new(rng.gen_range(0, 4)),
+                right_hand: i % 2 == 0,
+                cooldown: 0.,
+            })
+            .collect()
+    }
+
+    // True when it's time to move again
+    pub fn tick(&mut self, dt: f64, interval: f64) -> bool {
+        self.cooldown -= dt;
+        if self.cooldown > 0. {
+            return false;
+        }
+        self.cooldown += interval;
+        true
+    }
+
+    // Moves a cell towards the prey if any, otherwise onwards along the wall
+    pub fn step(&mut self, maze: &MazeGrid, prey: Option<Direction>) {
+        let heading = self.heading;
+        let along_wall = if self.right_hand {
+            [heading.next(), heading, heading.prev(), heading.opposite()]
+        } else {
+            [heading.prev(), heading, heading.next(), heading.opposite()]
+        };
+        let dir = prey.or_else(|| {
+            along_wall
+                .iter()
+                .find(|dir| maze.linked_cell(self.pos, **dir).is_some())
+                .copied()
+        });
+        if let Some(dir) = dir {
+            if let Some(cell) = maze.linked_cell(self.pos, dir) {
+                self.pos = cell.pos();
+                self.heading = dir;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_far_from_start() {
+        for seed in 0..10 {
+            let maze = MazeGrid::new(10, (0, 0), (9, 9), seed);
+            let monsters = Monster::spawn(&maze, 20);
+            assert_eq!(monsters.len(), 20);
+            for monster in monsters.iter() {
+                assert!(monster.pos.x + monster.pos.y >= 10);
+                assert_ne!(monster.pos, maze.end_pos());
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_patrol() {
+        let patrol = |seed| {
+            let maze = MazeGrid::new(10, (0, 0), (9, 9), seed);
+            let mut monsters = Monster::spawn(&maze, 3);
+            let mut positions = vec![];
+            for _ in 0..20 {
+                for monster in monsters.iter_mut() {
+                    monster.step(&maze, None);
+                    positions.push(monster.pos);
+                }
+            }
+            positions
+        };
+        assert_eq!(patrol(7), patrol(7));
+        assert_ne!(patrol(7), patrol(8));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::config::{Config, ListenAddr};
+use crate::custom_events::{handle_client, CustomEvent, MAX_AUDIO_BYTES};
+use crate::http;
+use log::error;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Error as StdError, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::Arc;
+use tiny_http::Server;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+use winit::event_loop::EventLoopProxy;
+
+// A client connection speaking the line based command protocol, whatever the transport
+pub trait Connection {
+    fn peer(&self) -> String;
+    // None once the client has hung up
+    fn read_line(&mut self) -> Result<Option<String>, StdError>;
+    fn write_line(&mut self, line: &str) -> Result<(), StdError>;
+    // Binary payload following a command, e.g. audio
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, StdError>;
+    // Connections that stay open by nature hold their players without asking, see handle_client
+    fn holds_players(&self) -> bool {
+        false
+    }
+}
+
+pub struct StreamConnection<S: Read + Write> {
+    peer: String,
+    reader: BufReader<S>,
+    writer: S,
+}
+
+impl<S: Read + Write> StreamConnection<S> {
+    pub fn new(peer: String, reader: S, writer: S) -> StreamConnection<S> {
+        StreamConnection {
+            peer,
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+}
+
+impl<S: Read + Write> Connection for StreamConnection<S> {
+    fn peer(&self) -> String {
+        self.peer.clone()
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, StdError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), StdError> {
+        writeln!(self.writer, "{}", line)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, StdError> {
+        let mut buf = vec![0; len];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+// Each websocket text message may hold one or more command lines
+pub struct WebSocketConnection {
+    peer: String,
+    socket: WebSocket<TcpStream>,
+    pending: VecDeque<String>,
+}
+
+impl Connection for WebSocketConnection {
+    fn peer(&self) -> String {
+        self.peer.clone()
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, StdError> {
+        while self.pending.is_empty() {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    self.pending.extend(text.lines().map(|l| l.to_string()));
+                }
+                Ok(Message::Close(_)) => return Ok(None),
+                Ok(_) => (),
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(None),
+                Err(e) => return Err(StdError::other(e)),
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), StdError> {
+        self.socket
+            .send(Message::Text(line.to_string()))
+            .map_err(StdError::other)
+    }
+
+    // Payload comes as the next binary message, of the announced length
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, StdError> {
+        match self.socket.read() {
+            Ok(Message::Binary(data)) if data.len() == len => Ok(data),
+            Ok(Message::Binary(data)) => Err(StdError::new(
+                ErrorKind::InvalidData,
+                format!("Expected {} bytes, got {}", len, data.len()),
+            )),
+            Ok(_) => Err(StdError::new(
+                ErrorKind::InvalidData,
+                "Expected a binary message",
+            )),
+            Err(e) => Err(StdError::other(e)),
+        }
+    }
+
+    fn holds_players(&self) -> bool {
+        true
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    WebSocket(TcpListener),
+    Http(Server),
+}
+
+impl Listener {
+    pub fn bind(addr: &ListenAddr) -> Result<Listener, String> {
+        let bind_error = |e: &dyn std::fmt::Display| format!("Failed to bind {}: {}", addr, e);
+        match addr {
+            ListenAddr::Tcp(socket_addr) => TcpListener::bind(socket_addr)
+                .map(Listener::Tcp)
+                .map_err(|e| bind_error(&e)),
+            ListenAddr::WebSocket(socket_addr) => TcpListener::bind(socket_addr)
+                .map(Listener::WebSocket)
+                .map_err(|e| bind_error(&e)),
+            ListenAddr::Http(socket_addr) => Server::http(socket_addr)
+                .map(Listener::Http)
+                .map_err(|e| bind_error(&e)),
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                // Leftover socket from a previous run would make bind fail, but one that still
+                // answers belongs to a running game
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        match UnixStream::connect(path) {
+                            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                                std::fs::remove_file(path).map_err(|e| bind_error(&e))?;
+                            }
+                            Ok(_) => return Err(bind_error(&"socket is in use")),
+                            Err(e) => return Err(bind_error(&e)),
+                        }
+                    }
+                }
+                UnixListener::bind(path)
+                    .map(Listener::Unix)
+                    .map_err(|e| bind_error(&e))
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => Err(bind_error(&"unix sockets are not supported")),
+        }
+    }
+
+    // Accepts connections on a background thread, each connection gets its own thread
+    // since subscribers keep theirs open
+    pub fn spawn(self, event_loop_proxy: EventLoopProxy<CustomEvent>, config: Arc<Config>) {
+        std::thread::spawn(move || match self {
+            Listener::Tcp(listener) => {
+                for stream in listener.incoming() {
+                    match stream.and_then(tcp_connection) {
+                        Ok(connection) => spawn_client(connection, &event_loop_proxy, &config),
+                        Err(e) => error!("Failed to accept tcp connection: {}", e),
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                for stream in listener.incoming() {
+                    match stream.and_then(unix_connection) {
+                        Ok(connection) => spawn_client(connection, &event_loop_proxy, &config),
+                        Err(e) => error!("Failed to accept unix connection: {}", e),
+                    }
+                }
+            }
+            Listener::WebSocket(listener) => {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let event_loop_proxy = event_loop_proxy.clone();
+                            let config = config.clone();
+                            // Handshake on the client's thread so a slow client can't block others
+                            std::thread::spawn(move || match websocket_connection(stream) {
+                                Ok(mut connection) => {
+                                    run_client(&mut connection, &event_loop_proxy, &config)
+                                }
+                                Err(e) => error!("Websocket handshake failed: {}", e),
+                            });
+                        }
+                        Err(e) => error!("Failed to accept websocket connection: {}", e),
+                    }
+                }
+            }
+            Listener::Http(server) => http::serve(server, event_loop_proxy, &config),
+        });
+    }
+}
+
+fn tcp_connection(stream: TcpStream) -> Result<StreamConnection<TcpStream>, StdError> {
+    let peer = stream.peer_addr()?.to_string();
+    Ok(StreamConnection::new(peer, stream.try_clone()?, stream))
+}
+
+#[cfg(unix)]
+fn unix_connection(stream: UnixStream) -> Result<StreamConnection<UnixStream>, StdError> {
+    Ok(StreamConnection::new(
+        "unix socket".to_string(),
+        stream.try_clone()?,
+        stream,
+    ))
+}
+
+fn websocket_connection(stream: TcpStream) -> Result<WebSocketConnection, StdError> {
+    let peer = stream.peer_addr()?.to_string();
+    // Nothing clients send is larger than a recording, bigger frames are refused before buffering
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_AUDIO_BYTES),
+        max_frame_size: Some(MAX_AUDIO_BYTES),
+        ..WebSocketConfig::default()
+    };
+    let socket = tungstenite::accept_with_config(stream, Some(config)).map_err(StdError::other)?;
+    Ok(WebSocketConnection {
+        peer,
+        socket,
+        pending: VecDeque::new(),
+    })
+}
+
+fn spawn_client<C: Connection + Send + 'static>(
+    mut connection: C,
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    config: &Arc<Config>,
+) {
+    let event_loop_proxy = event_loop_proxy.clone();
+    let config = config.clone();
+    std::thread::spawn(move || run_client(&mut connection, &event_loop_proxy, &config));
+}
+
+fn run_client<C: Connection>(
+    connection: &mut C,
+    event_loop_proxy: &EventLoopProxy<CustomEvent>,
+    config: &Config,
+) {
+    if let Err(e) = handle_client(connection, event_loop_proxy, config) {
+        error!("Failed to handle message from {}: {}", connection.peer(), e);
+    }
+}
@@ -0,0 +1,314 @@
+use crate::bots::BotLevel;
+use crate::coop::VoteRule;
+use crate::custom_events::ControlMode;
+use crate::direction::Direction;
+use crate::items::Item;
+use crate::teams::TeamScoring;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Lobby (players join & ready up) -> countdown -> playing (moves accepted) -> finished (results)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Lobby,
+    Countdown,
+    Playing,
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    // Everyone for themselves, first to the goal wins
+    Race,
+    // Everyone steers the team's shared avatar
+    Coop,
+    // One player is it and chases the others, who score the time they're not it
+    Tag,
+    // Score attack, most points of collected items when the time is up wins
+    Score,
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "race" => Ok(GameMode::Race),
+            "coop" => Ok(GameMode::Coop),
+            "tag" => Ok(GameMode::Tag),
+            "score" => Ok(GameMode::Score),
+            _ => Err(format!("Invalid game mode: {}", s)),
+        }
+    }
+}
+
+// Serializable snapshots of the game, handed out to clients (http api etc.)
+#[derive(Debug, Clone, Serialize)]
+pub struct GameState {
+    pub mode: GameMode,
+    pub phase: Phase,
+    // Milliseconds until the race starts, during the countdown
+    pub countdown: Option<u64>,
+    pub finished: bool,
+    pub winner: Option<String>,
+    // Milliseconds since the race started
+    pub race_time: u64,
+    // Race ends after this many milliseconds even if not everyone has finished
+    pub time_limit: Option<u64>,
+    pub rankings: Vec<Ranking>,
+    pub team_scoring: TeamScoring,
+    // Empty unless someone has joined a team
+    pub teams: Vec<TeamRanking>,
+    // None unless playing a tournament
+    pub tournament: Option<TournamentState>,
+    // None unless playing coop
+    pub coop: Option<CoopState>,
+    // Player chasing the others, in tag mode
+    pub it: Option<String>,
+    // Positions of the wandering monsters
+    pub monsters: Vec<[i32; 2]>,
+    pub paused: bool,
+    pub players: Vec<PlayerState>,
+    // Moves each player may undo per race, None if unlimited
+    pub undo_limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerState {
+    pub name: String,
+    pub color: [u8; 3],
+    pub x: i32,
+    pub y: i32,
+    pub heading: Direction,
+    pub control_mode: ControlMode,
+    // Moves undone this race
+    pub undos: u32,
+    // False while the player's connection has dropped & they may still reconnect
+    pub connected: bool,
+    // None unless the game moves the player by itself
+    pub bot: Option<BotLevel>,
+    // Ready to start, in the lobby
+    pub ready: bool,
+    // Race time when the player reached the goal
+    pub finish_time: Option<u64>,
+    // Move commands this race
+    pub moves: u32,
+    pub team: Option<String>,
+    // Milliseconds not being it, in tag mode
+    pub free_time: u64,
+    // Items collected this race & their points
+    pub items: u32,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ranking {
+    pub place: u32,
+    pub name: String,
+    // None if the player didn't finish
+    pub time: Option<u64>,
+    pub moves: u32,
+    // Result in modes without a goal, milliseconds not being it in tag & points in score attack
+    pub score: Option<u64>,
+}
+
+impl Ranking {
+    // Reached the goal, or got a score in a mode without one
+    pub fn is_scored(&self) -> bool {
+        self.time.is_some() || self.score.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TeamRanking {
+    pub place: u32,
+    pub name: String,
+    pub color: [u8; 3],
+    pub members: Vec<String>,
+    // Members who have reached the goal
+    pub finished: u32,
+    // Race time when the team reached the goal, by the team scoring
+    pub time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoopState {
+    pub rule: VoteRule,
+    // Whose move it is, with the turns rule
+    pub turn: Option<String>,
+    // Votes for the next move, with the majority rule
+    pub votes: Vec<Vote>,
+    // Who may move which way, with the directions rule
+    pub owners: Vec<DirectionOwner>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Vote {
+    pub name: String,
+    pub vote: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectionOwner {
+    pub direction: Direction,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentState {
+    pub round: u32,
+    pub rounds: u32,
+    // Last round has been played
+    pub over: bool,
+    // Most points first
+    pub standings: Vec<Standing>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Standing {
+    pub name: String,
+    pub points: u32,
+    // Rounds won
+    pub wins: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MazeState {
+    pub size: i32,
+    // Same size & seed generate the same maze
+    pub seed: u64,
+    pub start: [i32; 2],
+    pub end: [i32; 2],
+    pub cells: Vec<CellState>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CellState {
+    pub x: i32,
+    pub y: i32,
+    pub directions: Vec<Direction>,
+    pub item: Option<Item>,
+}
+
+// Events streamed to subscribers (spectators, bots, loggers), one json object per line
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GameEvent {
+    Snapshot {
+        state: Box<GameState>,
+        maze: MazeState,
+    },
+    PlayerJoined {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+    PlayerMoved {
+        name: String,
+        direction: Direction,
+        // Cells moved with a single command
+        steps: u32,
+        x: i32,
+        y: i32,
+    },
+    PlayerTurned {
+        name: String,
+        heading: Direction,
+    },
+    PlayerRewound {
+        name: String,
+        // Moves undone with a single command
+        moves: u32,
+        x: i32,
+        y: i32,
+    },
+    PlayerReady {
+        name: String,
+    },
+    // Team is None when leaving a team
+    PlayerJoinedTeam {
+        name: String,
+        team: Option<String>,
+    },
+    // Became it in tag mode, by None at the start of a round
+    PlayerTagged {
+        name: String,
+        by: Option<String>,
+    },
+    ItemCollected {
+        name: String,
+        item: Item,
+        x: i32,
+        y: i32,
+    },
+    MonsterMoved {
+        monster: usize,
+        x: i32,
+        y: i32,
+    },
+    // Sent back to start (x & y) or lost points
+    PlayerCaught {
+        name: String,
+        x: i32,
+        y: i32,
+        points_lost: u32,
+    },
+    // Vote for the team's next move, in coop mode
+    PlayerVoted {
+        name: String,
+        vote: String,
+    },
+    PhaseChanged {
+        phase: Phase,
+    },
+    PlayerRenamed {
+        name: String,
+        new_name: String,
+    },
+    // Sent back to start by an admin
+    PlayerReset {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+    // Connection dropped, player is kept for a while
+    PlayerDropped {
+        name: String,
+    },
+    PlayerReconnected {
+        name: String,
+    },
+    PlayerLeft {
+        name: String,
+    },
+    // First to finish
+    PlayerWon {
+        name: String,
+    },
+    PlayerFinished {
+        name: String,
+        place: u32,
+        time: u64,
+    },
+    TeamFinished {
+        team: String,
+        place: u32,
+        time: u64,
+    },
+    // Everyone finished or time ran out
+    RaceFinished {
+        rankings: Vec<Ranking>,
+        teams: Vec<TeamRanking>,
+    },
+    // Last round of a tournament has been played
+    TournamentFinished {
+        standings: Vec<Standing>,
+    },
+    MazeRegenerated {
+        maze: MazeState,
+    },
+    GamePaused,
+    GameResumed,
+}
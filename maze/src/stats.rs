@@ -0,0 +1,217 @@
+use crate::state::Ranking;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Results of every race played, kept across sessions in a json file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub players: Vec<PlayerStats>,
+    // Saved after every race, if given
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub name: String,
+    pub races: u32,
+    pub wins: u32,
+    // Move commands over all races
+    pub moves: u64,
+    // Races won in a row, up to the latest one
+    pub streak: u32,
+    pub best_streak: u32,
+    pub best_times: Vec<BestTime>,
+}
+
+// Fastest finish on a maze, which the size & seed generate again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BestTime {
+    pub size: i32,
+    pub seed: u64,
+    // Milliseconds
+    pub time: u64,
+    pub moves: u32,
+}
+
+impl Stats {
+    // Starts empty if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Stats, String> {
+        let mut stats = if path.exists() {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read stats {}: {}", path.display(), e))?;
+            serde_json::from_str::<Stats>(&contents)
+                .map_err(|e| format!("Invalid stats {}: {}", path.display(), e))?
+        } else {
+            Stats::default()
+        };
+        stats.path = Some(path.to_path_buf());
+        Ok(stats)
+    }
+
+    // Adds a finished race, first place wins if they reached the goal (or scored)
+    pub fn record(&mut self, rankings: &[Ranking], size: i32, seed: u64) {
+        for ranking in rankings.iter() {
+            let player = match self.players.iter().position(|p| p.name == ranking.name) {
+                Some(index) => &mut self.players[index],
+                None => {
+                    self.players.push(PlayerStats {
+                        name: ranking.name.clone(),
+                        ..PlayerStats::default()
+                    });
+                    self.players.last_mut().unwrap()
+                }
+            };
+            player.races += 1;
+            player.moves += ranking.moves as u64;
+            if ranking.place == 1 && ranking.is_scored() {
+                player.wins += 1;
+                player.streak += 1;
+                player.best_streak = player.best_streak.max(player.streak);
+            } else {
+                player.streak = 0;
+            }
+            if let Some(time) = ranking.time {
+                let best = BestTime {
+                    size,
+                    seed,
+                    time,
+                    moves: ranking.moves,
+                };
+                match player
+                    .best_times
+                    .iter_mut()
+                    .find(|b| b.size == size && b.seed == seed)
+                {
+                    Some(b) if time < b.time => *b = best,
+                    Some(_) => (),
+                    None => player.best_times.push(best),
+                }
+            }
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            serde_json::to_string_pretty(self)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()))
+                .unwrap_or_else(|e| error!("Failed to save stats {}: {}", path.display(), e));
+        }
+    }
+
+    // Most wins first, then longest streak, then fewest races
+    pub fn leaderboard(&self) -> Vec<PlayerStats> {
+        let mut players = self.players.clone();
+        players.sort_by(|a, b| {
+            b.wins
+                .cmp(&a.wins)
+                .then(b.best_streak.cmp(&a.best_streak))
+                .then(a.races.cmp(&b.races))
+                .then(a.name.cmp(&b.name))
+        });
+        players
+    }
+}
+
+impl PlayerStats {
+    // Fastest time on any maze of the size
+    pub fn best_time(&self, size: i32) -> Option<u64> {
+        self.best_times
+            .iter()
+            .filter(|b| b.size == size)
+            .map(|b| b.time)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Finishers by time in ms, None for players who didn't finish
+    fn race(results: &[(&str, Option<u64>)]) -> Vec<Ranking> {
+        results
+            .iter()
+            .enumerate()
+            .map(|(i, (name, time))| Ranking {
+                place: i as u32 + 1,
+                name: name.to_string(),
+                time: *time,
+                moves: 10,
+                score: None,
+            })
+            .collect()
+    }
+
+    fn player<'a>(stats: &'a Stats, name: &str) -> &'a PlayerStats {
+        stats.players.iter().find(|p| p.name == name).unwrap()
+    }
+
+    #[test]
+    fn records_races_and_wins() {
+        let mut stats = Stats::default();
+        stats.record(&race(&[("okko", Some(5000)), ("sini", None)]), 10, 1);
+        assert_eq!(player(&stats, "okko").races, 1);
+        assert_eq!(player(&stats, "okko").wins, 1);
+        assert_eq!(player(&stats, "okko").moves, 10);
+        assert_eq!(player(&stats, "sini").races, 1);
+        assert_eq!(player(&stats, "sini").wins, 0);
+        assert!(player(&stats, "sini").best_times.is_empty());
+    }
+
+    #[test]
+    fn nobody_wins_when_nobody_finishes() {
+        let mut stats = Stats::default();
+        stats.record(&race(&[("okko", None), ("sini", None)]), 10, 1);
+        assert_eq!(player(&stats, "okko").wins, 0);
+    }
+
+    #[test]
+    fn losing_ends_streak() {
+        let mut stats = Stats::default();
+        stats.record(&race(&[("okko", Some(5000)), ("sini", None)]), 10, 1);
+        stats.record(&race(&[("okko", Some(5000)), ("sini", None)]), 10, 2);
+        stats.record(&race(&[("sini", Some(5000)), ("okko", None)]), 10, 3);
+        stats.record(&race(&[("okko", Some(5000)), ("sini", None)]), 10, 4);
+        assert_eq!(player(&stats, "okko").streak, 1);
+        assert_eq!(player(&stats, "okko").best_streak, 2);
+        assert_eq!(player(&stats, "sini").streak, 0);
+        assert_eq!(player(&stats, "sini").best_streak, 1);
+    }
+
+    #[test]
+    fn best_time_is_kept_per_maze() {
+        let mut stats = Stats::default();
+        stats.record(&race(&[("okko", Some(5000))]), 10, 1);
+        stats.record(&race(&[("okko", Some(7000))]), 10, 1);
+        stats.record(&race(&[("okko", Some(3000))]), 10, 2);
+        stats.record(&race(&[("okko", Some(2000))]), 20, 1);
+        let okko = player(&stats, "okko");
+        assert_eq!(okko.best_times.len(), 3);
+        let times = okko
+            .best_times
+            .iter()
+            .map(|b| (b.size, b.seed, b.time))
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![(10, 1, 5000), (10, 2, 3000), (20, 1, 2000)]);
+        assert_eq!(okko.best_time(10), Some(3000));
+        assert_eq!(okko.best_time(30), None);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("maze-stats-{}.json", std::process::id()));
+        let mut stats = Stats::load(&path).unwrap();
+        assert!(stats.players.is_empty());
+        stats.record(&race(&[("okko", Some(5000)), ("sini", None)]), 10, 1);
+        let loaded = Stats::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.players.len(), 2);
+        assert_eq!(player(&loaded, "okko").wins, 1);
+        assert_eq!(player(&loaded, "okko").best_time(10), Some(5000));
+    }
+}
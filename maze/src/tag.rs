@@ -0,0 +1,13 @@
+use rand::prelude::SliceRandom;
+use rand::thread_rng;
+
+// Milliseconds a round of tag lasts, unless a time limit is given
+pub const ROUND_LENGTH: f64 = 120000.;
+
+// Milliseconds a player who became it has to wait before chasing, so they can't tag right back
+pub const TAG_FREEZE: f64 = 3000.;
+
+// Anyone of the players may start as it
+pub fn pick_it(players: &[String]) -> Option<String> {
+    players.choose(&mut thread_rng()).cloned()
+}
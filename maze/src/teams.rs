@@ -0,0 +1,49 @@
+use raqote::Color;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Colors of teams named after them, other names get one of these by their letters
+const TEAM_COLORS: [(&str, (u8, u8, u8)); 8] = [
+    ("red", (255, 60, 60)),
+    ("blue", (60, 100, 255)),
+    ("green", (60, 200, 60)),
+    ("yellow", (240, 220, 40)),
+    ("purple", (170, 70, 220)),
+    ("orange", (255, 150, 40)),
+    ("pink", (255, 120, 200)),
+    ("cyan", (40, 220, 220)),
+];
+
+// When a team has reached the goal
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TeamScoring {
+    // Once every member has, the team's time is the last member's
+    All,
+    // Once any member has, the team's time is the first member's
+    First,
+}
+
+impl FromStr for TeamScoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(TeamScoring::All),
+            "first" => Ok(TeamScoring::First),
+            _ => Err(format!("Invalid team scoring: {}", s)),
+        }
+    }
+}
+
+pub fn team_color(team: &str) -> Color {
+    let (_, (r, g, b)) = TEAM_COLORS
+        .iter()
+        .find(|(name, _)| *name == team)
+        .copied()
+        .unwrap_or_else(|| {
+            let letters = team.bytes().map(|b| b as usize).sum::<usize>();
+            TEAM_COLORS[letters % TEAM_COLORS.len()]
+        });
+    Color::new(255, r, g, b)
+}
@@ -0,0 +1,218 @@
+use crate::config::MAX_MAZE_SIZE;
+use crate::state::{Ranking, Standing, TournamentState};
+
+// Points for 1st, 2nd, 3rd... place in a round, by default
+pub const POINTS: [u32; 6] = [10, 7, 5, 3, 2, 1];
+
+// Cells added to the maze size each round, by default
+pub const SIZE_STEP: i32 = 4;
+
+// Several races in a row on growing mazes, placements give points that add up over the rounds
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    rounds: u32,
+    // Maze size of the first round
+    first_size: i32,
+    size_step: i32,
+    points: Vec<u32>,
+    // Starting from 1
+    round: u32,
+    // Rounds scored so far
+    scored: u32,
+    standings: Vec<Standing>,
+}
+
+impl Tournament {
+    pub fn new(rounds: u32, first_size: i32, size_step: i32, points: Vec<u32>) -> Tournament {
+        Tournament {
+            rounds,
+            first_size,
+            size_step,
+            points,
+            round: 1,
+            scored: 0,
+            standings: vec![],
+        }
+    }
+
+    // "10,7,5,3"
+    pub fn parse_points(value: &str) -> Result<Vec<u32>, String> {
+        value
+            .split(',')
+            .map(|points| {
+                points
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid points: {}", value))
+            })
+            .collect()
+    }
+
+    pub fn maze_size(&self) -> i32 {
+        (self.first_size + (self.round as i32 - 1) * self.size_step).min(MAX_MAZE_SIZE)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.scored == self.rounds
+    }
+
+    // Gives points for the round's placements, players who didn't finish (or score) get none
+    pub fn score(&mut self, rankings: &[Ranking]) {
+        if self.scored == self.round {
+            return;
+        }
+        for ranking in rankings.iter() {
+            let standing = match self.standings.iter().position(|s| s.name == ranking.name) {
+                Some(index) => &mut self.standings[index],
+                None => {
+                    self.standings.push(Standing {
+                        name: ranking.name.clone(),
+                        points: 0,
+                        wins: 0,
+                    });
+                    self.standings.last_mut().unwrap()
+                }
+            };
+            if ranking.is_scored() {
+                standing.points += self
+                    .points
+                    .get(ranking.place as usize - 1)
+                    .copied()
+                    .unwrap_or(0);
+                if ranking.place == 1 {
+                    standing.wins += 1;
+                }
+            }
+        }
+        self.standings.sort_by(|a, b| {
+            b.points
+                .cmp(&a.points)
+                .then(b.wins.cmp(&a.wins))
+                .then(a.name.cmp(&b.name))
+        });
+        self.scored = self.round;
+    }
+
+    // On to the next round once this one is scored, or a new tournament after the last one
+    pub fn next_round(&mut self) {
+        if self.is_over() {
+            self.round = 1;
+            self.scored = 0;
+            self.standings.clear();
+        } else if self.scored == self.round {
+            self.round += 1;
+        }
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) {
+        for standing in self.standings.iter_mut().filter(|s| s.name == name) {
+            standing.name = new_name.to_string();
+        }
+    }
+
+    pub fn state(&self) -> TournamentState {
+        TournamentState {
+            round: self.round,
+            rounds: self.rounds,
+            over: self.is_over(),
+            standings: self.standings.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(place: u32, name: &str, time: Option<u64>) -> Ranking {
+        Ranking {
+            place,
+            name: name.to_string(),
+            time,
+            moves: 10,
+            score: None,
+        }
+    }
+
+    fn points(tournament: &Tournament) -> Vec<(String, u32, u32)> {
+        tournament
+            .state()
+            .standings
+            .into_iter()
+            .map(|s| (s.name, s.points, s.wins))
+            .collect()
+    }
+
+    #[test]
+    fn scores_placements() {
+        let mut tournament = Tournament::new(2, 10, SIZE_STEP, POINTS.to_vec());
+        tournament.score(&[
+            ranking(1, "okko", Some(1000)),
+            ranking(2, "ville", Some(2000)),
+            ranking(3, "aino", None),
+        ]);
+        assert_eq!(
+            points(&tournament),
+            vec![
+                ("okko".to_string(), 10, 1),
+                ("ville".to_string(), 7, 0),
+                ("aino".to_string(), 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn scores_each_round_once() {
+        let mut tournament = Tournament::new(2, 10, SIZE_STEP, POINTS.to_vec());
+        let rankings = [
+            ranking(1, "okko", Some(1000)),
+            ranking(2, "ville", Some(2000)),
+        ];
+        tournament.score(&rankings);
+        tournament.score(&rankings);
+        assert_eq!(
+            points(&tournament),
+            vec![("okko".to_string(), 10, 1), ("ville".to_string(), 7, 0)]
+        );
+    }
+
+    #[test]
+    fn moves_on_only_after_scoring() {
+        let mut tournament = Tournament::new(2, 10, SIZE_STEP, POINTS.to_vec());
+        tournament.next_round();
+        assert_eq!(tournament.state().round, 1);
+        tournament.score(&[ranking(1, "okko", Some(1000))]);
+        tournament.next_round();
+        assert_eq!(tournament.state().round, 2);
+        assert_eq!(tournament.maze_size(), 10 + SIZE_STEP);
+    }
+
+    #[test]
+    fn starts_over_after_the_last_round() {
+        let mut tournament = Tournament::new(2, 10, SIZE_STEP, POINTS.to_vec());
+        tournament.score(&[ranking(1, "okko", Some(1000))]);
+        tournament.next_round();
+        tournament.score(&[ranking(1, "ville", Some(1000))]);
+        assert!(tournament.is_over());
+        tournament.next_round();
+        let state = tournament.state();
+        assert!(!state.over);
+        assert_eq!(state.round, 1);
+        assert!(state.standings.is_empty());
+        assert_eq!(tournament.maze_size(), 10);
+    }
+
+    #[test]
+    fn maze_size_is_capped() {
+        let mut tournament = Tournament::new(20, MAX_MAZE_SIZE - 1, SIZE_STEP, POINTS.to_vec());
+        tournament.score(&[]);
+        tournament.next_round();
+        assert_eq!(tournament.maze_size(), MAX_MAZE_SIZE);
+    }
+
+    #[test]
+    fn parses_points() {
+        assert_eq!(Tournament::parse_points("10, 7,5"), Ok(vec![10, 7, 5]));
+        assert!(Tournament::parse_points("10,seven").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Actions (and action words) the command protocol understands
+const ACTIONS: [&str; 23] = [
+    "connect",
+    "disconnect",
+    "reconnect",
+    "ready",
+    "team",
+    "up",
+    "right",
+    "down",
+    "left",
+    "forward",
+    "back",
+    "turn left",
+    "turn right",
+    "run",
+    "mode",
+    "relative",
+    "absolute",
+    "subscribe",
+    "hold",
+    "stats",
+    "audio",
+    "enroll",
+    "undo",
+];
+
+// Built in aliases, Finnish since the speech model is trained on our own recordings
+const DEFAULT_ALIASES: [(&str, &[&str]); 16] = [
+    ("connect", &["liity"]),
+    ("disconnect", &["poistu"]),
+    ("ready", &["valmis"]),
+    ("team", &["joukkue"]),
+    ("up", &["ylös", "ylos"]),
+    ("right", &["oikea", "oikealle"]),
+    ("down", &["alas"]),
+    ("left", &["vasen", "vasemmalle"]),
+    ("forward", &["eteen", "eteenpäin"]),
+    ("back", &["backward", "backwards", "taakse", "taaksepäin"]),
+    ("turn left", &["käänny vasemmalle", "käänny vasen"]),
+    ("turn right", &["käänny oikealle", "käänny oikea"]),
+    ("run", &["juokse"]),
+    ("undo", &["peru", "kumoa"]),
+    ("relative", &["suhteellinen"]),
+    ("absolute", &["absoluuttinen"]),
+];
+
+// Maps spoken/typed words & phrases to the protocol's action words
+#[derive(Debug, Clone)]
+pub struct Vocabulary {
+    phrases: HashMap<String, String>,
+}
+
+impl Vocabulary {
+    pub fn new() -> Vocabulary {
+        let mut vocabulary = Vocabulary {
+            phrases: HashMap::new(),
+        };
+        for action in ACTIONS.iter() {
+            vocabulary.add(action, action);
+        }
+        for (action, aliases) in DEFAULT_ALIASES.iter() {
+            for alias in aliases.iter() {
+                vocabulary.add(alias, action);
+            }
+        }
+        vocabulary
+    }
+
+    // Adds aliases from a json file like {"up": ["upp", "norr"], "turn left": ["sväng vänster"]}
+    pub fn load(path: &Path) -> Result<Vocabulary, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read vocabulary {}: {}", path.display(), e))?;
+        let aliases = serde_json::from_str::<HashMap<String, Vec<String>>>(&contents)
+            .map_err(|e| format!("Invalid vocabulary {}: {}", path.display(), e))?;
+        let mut vocabulary = Vocabulary::new();
+        for (action, phrases) in aliases.iter() {
+            if !ACTIONS.contains(&action.as_str()) {
+                return Err(format!("Unknown action in vocabulary: {}", action));
+            }
+            for phrase in phrases.iter() {
+                vocabulary.add(phrase, action);
+            }
+        }
+        Ok(vocabulary)
+    }
+
+    fn add(&mut self, phrase: &str, action: &str) {
+        let phrase = phrase
+            .to_lowercase()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        self.phrases.insert(phrase, action.to_string());
+    }
+
+    // Replaces known phrases (longest match first) with their actions, other words are kept as is.
+    // E.g. "juokse vasemmalle" -> "run left", "käänny oikealle" -> "turn right"
+    pub fn translate(&self, text: &str) -> String {
+        let lowercase = text.to_lowercase();
+        let words = lowercase.split_whitespace().collect::<Vec<&str>>();
+        let mut translated = vec![];
+        let mut i = 0;
+        while i < words.len() {
+            let matched = (i + 1..=words.len()).rev().find_map(|end| {
+                self.phrases
+                    .get(&words[i..end].join(" "))
+                    .map(|action| (end, action))
+            });
+            match matched {
+                Some((end, action)) => {
+                    translated.push(action.as_str());
+                    i = end;
+                }
+                None => {
+                    translated.push(words[i]);
+                    i += 1;
+                }
+            }
+        }
+        translated.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_actions() {
+        let vocabulary = Vocabulary::new();
+        assert_eq!(vocabulary.translate("left"), "left");
+        assert_eq!(vocabulary.translate("turn left"), "turn left");
+    }
+
+    #[test]
+    fn translates_finnish_aliases() {
+        let vocabulary = Vocabulary::new();
+        assert_eq!(vocabulary.translate("vasen"), "left");
+        assert_eq!(vocabulary.translate("Ylös"), "up");
+        assert_eq!(vocabulary.translate("taaksepäin"), "back");
+        assert_eq!(vocabulary.translate("peru 3"), "undo 3");
+    }
+
+    #[test]
+    fn translates_phrases_longest_first() {
+        let vocabulary = Vocabulary::new();
+        assert_eq!(vocabulary.translate("käänny oikealle"), "turn right");
+        assert_eq!(vocabulary.translate("juokse vasemmalle"), "run left");
+        assert_eq!(vocabulary.translate("käänny  vasen"), "turn left");
+        // "käänny" alone is no phrase, so it is kept as is
+        assert_eq!(vocabulary.translate("käänny ylös"), "käänny up");
+    }
+
+    #[test]
+    fn keeps_unknown_words() {
+        let vocabulary = Vocabulary::new();
+        assert_eq!(vocabulary.translate(""), "");
+        assert_eq!(vocabulary.translate("   "), "");
+        assert_eq!(vocabulary.translate("sideways 3"), "sideways 3");
+        assert_eq!(vocabulary.translate("left@0.5"), "left@0.5");
+    }
+}
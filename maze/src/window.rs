@@ -24,7 +24,7 @@ impl AppWindow {
             .with_resizable(false)
             .with_visible(false)
             .with_title(title)
-            .build(&event_loop)
+            .build(event_loop)
             .unwrap();
         let hidpi_factor = window.scale_factor();
         let (monitor_width, monitor_height) = {